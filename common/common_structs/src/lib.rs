@@ -3,6 +3,8 @@
 multiversx_sc::imports!();
 multiversx_sc::derive_imports!();
 
+pub mod subtract_error;
 pub mod unique_payments;
 
+pub use subtract_error::*;
 pub use unique_payments::*;
@@ -0,0 +1,41 @@
+multiversx_sc::derive_imports!();
+
+#[derive(
+    TypeAbi,
+    TopEncode,
+    TopDecode,
+    NestedEncode,
+    NestedDecode,
+    ManagedVecItem,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Debug,
+)]
+pub enum SubtractError {
+    NotSubscribed,
+    NotDueYet,
+    InvalidServiceInfo,
+    UserUnknown,
+    NoDeposits,
+    TokenNotDeposited,
+    InsufficientBalance,
+    PriceQueryFailed,
+    TokenNotAllowed,
+    SpendingCapExceeded,
+}
+
+impl SubtractError {
+    /// Errors caused by the user's funds or limits, as opposed to the service or the price sources
+    pub fn is_balance_error(&self) -> bool {
+        matches!(
+            self,
+            SubtractError::NoDeposits
+                | SubtractError::TokenNotDeposited
+                | SubtractError::InsufficientBalance
+                | SubtractError::TokenNotAllowed
+                | SubtractError::SpendingCapExceeded
+        )
+    }
+}
@@ -3,6 +3,8 @@ multiversx_sc::derive_imports!();
 
 use mergeable::Mergeable;
 
+use crate::SubtractError;
+
 pub type PaymentsVec<M> = ManagedVec<M, EsdtTokenPayment<M>>;
 
 #[derive(TypeAbi, TopEncode, TopDecode, NestedEncode, NestedDecode, Clone, PartialEq, Debug)]
@@ -59,8 +61,8 @@ impl<M: ManagedTypeApi> UniquePayments<M> {
 
         self.payments.push(new_payment);
     }
-    #[allow(clippy::result_unit_err)]
-    pub fn deduct_payment(&mut self, payment: &EsdtTokenPayment<M>) -> Result<(), ()> {
+
+    pub fn deduct_payment(&mut self, payment: &EsdtTokenPayment<M>) -> Result<(), SubtractError> {
         if payment.amount == 0 {
             return Result::Ok(());
         }
@@ -73,7 +75,7 @@ impl<M: ManagedTypeApi> UniquePayments<M> {
             }
 
             if current_payment.amount < payment.amount {
-                return Result::Err(());
+                return Result::Err(SubtractError::InsufficientBalance);
             }
 
             current_payment.amount -= &payment.amount;
@@ -82,7 +84,7 @@ impl<M: ManagedTypeApi> UniquePayments<M> {
             return Result::Ok(());
         }
 
-        Result::Err(())
+        Result::Err(SubtractError::TokenNotDeposited)
     }
 
     #[inline]
@@ -2,7 +2,7 @@
 
 use subscription_fee::{
     service::ProxyTrait as _,
-    subtract_payments::{Epoch, ProxyTrait as _, ScResult},
+    subtract_payments::{Epoch, ProxyTrait as _, ScResult, SubtractError},
 };
 
 multiversx_sc::imports!();
@@ -63,20 +63,25 @@ pub trait CommonSubscriberModule {
         fees_contract_address: ManagedAddress,
         service_index: usize,
         user_id: AddressId,
-    ) -> ScResult<EsdtTokenPayment, ()> {
+    ) -> ScResult<EsdtTokenPayment, SubtractError> {
         let fees_mapper = self.user_fees(service_index, user_id);
         require!(fees_mapper.is_empty(), "User last fees not processed yet");
 
         let subtract_result =
             self.call_subtract_payment(fees_contract_address, service_index, user_id);
-        if let ScResult::Ok(fees) = subtract_result.clone() {
-            let current_epoch = self.blockchain().get_block_epoch();
-            let user_fees = UserFees {
-                fees,
-                epoch: current_epoch,
-            };
-
-            fees_mapper.set(user_fees);
+        let current_epoch = self.blockchain().get_block_epoch();
+        match subtract_result.clone() {
+            ScResult::Ok(fees) => {
+                let user_fees = UserFees {
+                    fees,
+                    epoch: current_epoch,
+                };
+
+                fees_mapper.set(user_fees);
+            }
+            ScResult::Err(error) => {
+                self.subtract_payment_failed_event(current_epoch, service_index, user_id, error);
+            }
         }
 
         subtract_result
@@ -87,7 +92,7 @@ pub trait CommonSubscriberModule {
         fee_contract_address: ManagedAddress,
         service_index: usize,
         user_id: AddressId,
-    ) -> ScResult<EsdtTokenPayment, ()> {
+    ) -> ScResult<EsdtTokenPayment, SubtractError> {
         self.fee_contract_proxy_obj(fee_contract_address)
             .subtract_payment(service_index, user_id)
             .execute_on_dest_context()
@@ -98,6 +103,15 @@ pub trait CommonSubscriberModule {
         self.fee_contract_proxy_obj(fees_contract_address)
     }
 
+    #[event("subtractPaymentFailedEvent")]
+    fn subtract_payment_failed_event(
+        &self,
+        #[indexed] epoch: Epoch,
+        #[indexed] service_index: usize,
+        #[indexed] user_id: AddressId,
+        error: SubtractError,
+    );
+
     #[proxy]
     fn fee_contract_proxy_obj(
         &self,
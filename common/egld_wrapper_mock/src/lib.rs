@@ -0,0 +1,34 @@
+#![no_std]
+
+multiversx_sc::imports!();
+
+/// Local stand-in for the EGLD wrapping contract, used in tests.
+/// Mints the wrapped token 1:1 for the received EGLD, so it needs the local mint role.
+#[multiversx_sc::contract]
+pub trait EgldWrapperMock {
+    #[init]
+    fn init(&self, wrapped_egld_token_id: TokenIdentifier) {
+        self.wrapped_egld_token_id().set(wrapped_egld_token_id);
+    }
+
+    #[payable("EGLD")]
+    #[endpoint(wrapEgld)]
+    fn wrap_egld(&self) -> EsdtTokenPayment {
+        let payment_amount = self.call_value().egld_value().clone_value();
+        require!(payment_amount > 0, "Payment must be more than 0");
+
+        let wrapped_egld_token_id = self.wrapped_egld_token_id().get();
+        self.send()
+            .esdt_local_mint(&wrapped_egld_token_id, 0, &payment_amount);
+
+        let caller = self.blockchain().get_caller();
+        self.send()
+            .direct_esdt(&caller, &wrapped_egld_token_id, 0, &payment_amount);
+
+        EsdtTokenPayment::new(wrapped_egld_token_id, 0, payment_amount)
+    }
+
+    #[view(getWrappedEgldTokenId)]
+    #[storage_mapper("wrappedEgldTokenId")]
+    fn wrapped_egld_token_id(&self) -> SingleValueMapper<TokenIdentifier>;
+}
@@ -0,0 +1,69 @@
+#![no_std]
+
+multiversx_sc::imports!();
+multiversx_sc::derive_imports!();
+
+#[derive(TypeAbi, TopEncode, TopDecode, NestedEncode, NestedDecode)]
+pub struct PriceFeed<M: ManagedTypeApi> {
+    pub round_id: u32,
+    pub from: ManagedBuffer<M>,
+    pub to: ManagedBuffer<M>,
+    pub timestamp: u64,
+    pub price: BigUint<M>,
+    pub decimals: u8,
+}
+
+/// Local stand-in for the price aggregator contract, used in tests.
+/// Returns the last price set for each pair of feed names.
+#[multiversx_sc::contract]
+pub trait PriceAggregatorMock {
+    #[init]
+    fn init(&self) {}
+
+    #[endpoint(setLatestPriceFeed)]
+    fn set_latest_price_feed(
+        &self,
+        from: ManagedBuffer,
+        to: ManagedBuffer,
+        price: BigUint,
+        decimals: u8,
+        timestamp: u64,
+    ) {
+        let price_feed_mapper = self.price_feed(&from, &to);
+        let round_id = if price_feed_mapper.is_empty() {
+            1
+        } else {
+            price_feed_mapper.get().round_id + 1
+        };
+
+        price_feed_mapper.set(PriceFeed {
+            round_id,
+            from,
+            to,
+            timestamp,
+            price,
+            decimals,
+        });
+    }
+
+    #[view(latestPriceFeedOptional)]
+    fn latest_price_feed_optional(
+        &self,
+        from: ManagedBuffer,
+        to: ManagedBuffer,
+    ) -> OptionalValue<PriceFeed<Self::Api>> {
+        let price_feed_mapper = self.price_feed(&from, &to);
+        if price_feed_mapper.is_empty() {
+            return OptionalValue::None;
+        }
+
+        OptionalValue::Some(price_feed_mapper.get())
+    }
+
+    #[storage_mapper("priceFeed")]
+    fn price_feed(
+        &self,
+        from: &ManagedBuffer,
+        to: &ManagedBuffer,
+    ) -> SingleValueMapper<PriceFeed<Self::Api>>;
+}
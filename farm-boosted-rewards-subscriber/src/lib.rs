@@ -3,7 +3,7 @@
 multiversx_sc::imports!();
 multiversx_sc::derive_imports!();
 
-use multiversx_sc_modules::only_admin;
+use multiversx_sc_modules::{only_admin, pause};
 use subscriber_config::MexActionsPercentages;
 use subscription_fee::subtract_payments::Epoch;
 
@@ -21,6 +21,7 @@ pub trait SubscriberContractMain:
     + energy_query::EnergyQueryModule
     + events::EventsModule
     + only_admin::OnlyAdminModule
+    + pause::PauseModule
 {
     /// Percentages must add up to 10,000 each, where 10,000 = 100%
     /// Lock period is number of epochs the tokens should be locked for
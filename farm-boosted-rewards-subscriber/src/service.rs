@@ -1,7 +1,7 @@
 multiversx_sc::imports!();
 multiversx_sc::derive_imports!();
 
-use multiversx_sc_modules::only_admin;
+use multiversx_sc_modules::{only_admin, pause};
 
 pub const STANDARD_SUBSCRIPTION_INDEX: usize = 0;
 pub const PREMIUM_SUBSCRIPTION_INDEX: usize = 1;
@@ -34,10 +34,12 @@ pub trait ServiceModule:
     + energy_query::EnergyQueryModule
     + events::EventsModule
     + only_admin::OnlyAdminModule
+    + pause::PauseModule
 {
     #[endpoint(subtractPayment)]
     fn subtract_payment_endpoint(&self, user_ids: MultiValueEncoded<AddressId>) {
         self.require_caller_is_admin();
+        self.require_not_paused();
         let current_epoch = self.blockchain().get_block_epoch();
         let payment_recurrency = PAYMENT_RECURRENCY;
         let standard_service_index = STANDARD_SUBSCRIPTION_INDEX;
@@ -72,26 +74,32 @@ pub trait ServiceModule:
             let user_energy = self.get_energy_amount(&user);
 
             let user_service_index = if user_energy >= energy_threshold {
-                premium_processed_user_ids.push(user_id);
                 premium_service_index
             } else {
-                standard_processed_user_ids.push(user_id);
                 standard_service_index
             };
 
+            // the failure reason is emitted by subtract_user_payment
             let subtract_user_payment_result = self.subtract_user_payment(
                 fees_contract_address.clone(),
                 user_service_index,
                 user_id,
             );
+            if subtract_user_payment_result.is_err() {
+                continue;
+            }
 
-            if !subtract_user_payment_result.is_err() {
-                user_last_payment = UserLastPayment {
-                    service_index: user_service_index,
-                    epoch: current_epoch,
-                };
-                user_last_payment_mapper.set(user_last_payment);
+            if user_service_index == premium_service_index {
+                premium_processed_user_ids.push(user_id);
+            } else {
+                standard_processed_user_ids.push(user_id);
             }
+
+            user_last_payment = UserLastPayment {
+                service_index: user_service_index,
+                epoch: current_epoch,
+            };
+            user_last_payment_mapper.set(user_last_payment);
         }
 
         if !premium_processed_user_ids.is_empty() {
@@ -148,6 +156,7 @@ pub trait ServiceModule:
         user_ids: MultiValueEncoded<AddressId>,
     ) {
         self.require_caller_is_admin();
+        self.require_not_paused();
         let actions_percentage = if service_index == STANDARD_SUBSCRIPTION_INDEX {
             self.normal_user_percentage().get()
         } else if service_index == PREMIUM_SUBSCRIPTION_INDEX {
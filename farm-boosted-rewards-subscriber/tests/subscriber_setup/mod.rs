@@ -15,6 +15,7 @@ use multiversx_sc::{
     storage::mappers::AddressId,
     types::{Address, EsdtLocalRole, ManagedVec, MultiValueEncoded},
 };
+use multiversx_sc_modules::pause::PauseModule;
 use multiversx_sc_scenario::{
     managed_address, managed_biguint, managed_token_id, rust_biguint,
     testing_framework::{BlockchainStateWrapper, ContractObjWrapper, TxResult},
@@ -212,6 +213,28 @@ where
         )
     }
 
+    pub fn call_pause(&mut self) -> TxResult {
+        self.b_mock.borrow_mut().execute_tx(
+            &self.owner_addr,
+            &self.sub_wrapper,
+            &rust_biguint!(0),
+            |sc| {
+                sc.pause_endpoint();
+            },
+        )
+    }
+
+    pub fn call_unpause(&mut self) -> TxResult {
+        self.b_mock.borrow_mut().execute_tx(
+            &self.owner_addr,
+            &self.sub_wrapper,
+            &rust_biguint!(0),
+            |sc| {
+                sc.unpause_endpoint();
+            },
+        )
+    }
+
     pub fn call_add_max_fee_withdraw_per_week(&mut self, max_amount_per_week: u64) -> TxResult {
         self.b_mock.borrow_mut().execute_tx(
             &self.owner_addr,
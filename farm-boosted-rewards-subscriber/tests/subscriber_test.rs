@@ -790,6 +790,169 @@ fn mex_operation_with_claim_fees_test() {
     );
 }
 
+#[test]
+fn subtract_payment_with_trial_user_test() {
+    let (
+        b_mock_rc,
+        _mex_pair_setup,
+        _stable_pair_setup,
+        _farm_setup,
+        mut subscription_setup,
+        mut subscriber_setup,
+    ) = init_all(
+        pair::contract_obj,
+        farm_with_locked_rewards::contract_obj,
+        energy_factory::contract_obj,
+        subscription_fee::contract_obj,
+        farm_boosted_rewards_subscriber::contract_obj,
+    );
+
+    let first_user = b_mock_rc
+        .borrow_mut()
+        .create_user_account(&rust_biguint!(0));
+    let second_user = b_mock_rc
+        .borrow_mut()
+        .create_user_account(&rust_biguint!(0));
+    let first_user_id = 1;
+    let second_user_id = 2;
+
+    b_mock_rc.borrow_mut().set_block_epoch(2);
+
+    subscriber_setup
+        .call_register_service(vec![
+            (
+                Some(WEGLD_TOKEN_ID.to_vec()),
+                1_000,
+                false,
+                WEEKLY_SUBSCRIPTION_EPOCHS,
+            ),
+            (
+                Some(WEGLD_TOKEN_ID.to_vec()),
+                500,
+                false,
+                WEEKLY_SUBSCRIPTION_EPOCHS,
+            ),
+        ])
+        .assert_ok();
+
+    subscription_setup
+        .call_approve_service(subscriber_setup.sub_wrapper.address_ref())
+        .assert_ok();
+
+    for user in [&first_user, &second_user].iter().copied() {
+        b_mock_rc
+            .borrow_mut()
+            .set_esdt_balance(user, WEGLD_TOKEN_ID, &rust_biguint!(1_000_000));
+
+        subscription_setup
+            .call_deposit(user, WEGLD_TOKEN_ID, 1_000_000)
+            .assert_ok();
+    }
+
+    subscription_setup
+        .call_subscribe(&first_user, vec![(1, STANDARD_SERVICE)])
+        .assert_ok();
+
+    // only the second user gets the trial
+    subscription_setup
+        .call_set_trial_period(
+            subscriber_setup.sub_wrapper.address_ref(),
+            STANDARD_SERVICE,
+            WEEKLY_SUBSCRIPTION_EPOCHS,
+        )
+        .assert_ok();
+    subscription_setup
+        .call_subscribe(&second_user, vec![(1, STANDARD_SERVICE)])
+        .assert_ok();
+
+    // the user in trial is skipped instead of failing the whole batch
+    subscriber_setup
+        .call_subtract_payment(vec![first_user_id, second_user_id])
+        .assert_ok();
+
+    b_mock_rc.borrow().check_esdt_balance(
+        subscriber_setup.sub_wrapper.address_ref(),
+        WEGLD_TOKEN_ID,
+        &rust_biguint!(1_000),
+    );
+}
+
+#[test]
+fn pause_test() {
+    let (
+        b_mock_rc,
+        _mex_pair_setup,
+        _stable_pair_setup,
+        _farm_setup,
+        mut subscription_setup,
+        mut subscriber_setup,
+    ) = init_all(
+        pair::contract_obj,
+        farm_with_locked_rewards::contract_obj,
+        energy_factory::contract_obj,
+        subscription_fee::contract_obj,
+        farm_boosted_rewards_subscriber::contract_obj,
+    );
+
+    let user = b_mock_rc
+        .borrow_mut()
+        .create_user_account(&rust_biguint!(0));
+    let user_id = 1;
+
+    b_mock_rc.borrow_mut().set_block_epoch(2);
+
+    subscriber_setup
+        .call_register_service(vec![
+            (
+                Some(WEGLD_TOKEN_ID.to_vec()),
+                1_000,
+                false,
+                WEEKLY_SUBSCRIPTION_EPOCHS,
+            ),
+            (
+                Some(WEGLD_TOKEN_ID.to_vec()),
+                500,
+                false,
+                WEEKLY_SUBSCRIPTION_EPOCHS,
+            ),
+        ])
+        .assert_ok();
+
+    subscription_setup
+        .call_approve_service(subscriber_setup.sub_wrapper.address_ref())
+        .assert_ok();
+
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, WEGLD_TOKEN_ID, &rust_biguint!(1_000_000));
+
+    subscription_setup
+        .call_deposit(&user, WEGLD_TOKEN_ID, 1_000_000)
+        .assert_ok();
+
+    subscription_setup
+        .call_subscribe(&user, vec![(1, STANDARD_SERVICE)])
+        .assert_ok();
+
+    subscriber_setup.call_pause().assert_ok();
+
+    subscriber_setup
+        .call_subtract_payment(vec![user_id])
+        .assert_user_error("Contract is paused");
+    subscriber_setup
+        .call_perform_mex_operation(STANDARD_SERVICE, vec![user_id])
+        .assert_user_error("Contract is paused");
+
+    subscriber_setup.call_unpause().assert_ok();
+
+    subscriber_setup
+        .call_subtract_payment(vec![user_id])
+        .assert_ok();
+    subscriber_setup
+        .call_perform_mex_operation(STANDARD_SERVICE, vec![user_id])
+        .assert_ok();
+}
+
 #[test]
 fn subtract_worth_of_stable_payment_test() {
     let (
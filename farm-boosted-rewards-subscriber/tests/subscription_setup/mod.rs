@@ -94,6 +94,22 @@ where
         )
     }
 
+    pub fn call_set_trial_period(
+        &mut self,
+        service_address: &Address,
+        service_index: usize,
+        trial_epochs: u64,
+    ) -> TxResult {
+        self.b_mock.borrow_mut().execute_tx(
+            service_address,
+            &self.s_wrapper,
+            &rust_biguint!(0),
+            |sc| {
+                sc.set_trial_period(service_index, trial_epochs);
+            },
+        )
+    }
+
     pub fn call_subscribe(&mut self, caller: &Address, args: Vec<(AddressId, usize)>) -> TxResult {
         self.b_mock
             .borrow_mut()
@@ -0,0 +1,232 @@
+multiversx_sc::imports!();
+multiversx_sc::derive_imports!();
+
+use crate::pair_actions::{pair_proxy::ProxyTrait as _, MAX_PERCENTAGE};
+
+pub const PAIR_ACTIVE_STATE: u8 = 1;
+
+#[derive(ManagedVecItem, Clone)]
+pub struct SwapHop<M: ManagedTypeApi> {
+    pub pair_address: ManagedAddress<M>,
+    pub token_out: TokenIdentifier<M>,
+}
+
+#[multiversx_sc::module]
+pub trait AutoSwapModule:
+    crate::fees::FeesModule
+    + crate::pair_actions::PairActionsModule
+    + crate::common_storage::CommonStorageModule
+    + crate::events::EventsModule
+    + multiversx_sc_modules::pause::PauseModule
+{
+    /// When a service requires a token the caller does not have enough of, their other deposited tokens
+    /// are swapped through the pairs to cover the missing amount. Excluded tokens are never swapped.
+    /// max_slippage is relative to the safe price, where 10,000 = 100%.
+    #[endpoint(enableAutoSwap)]
+    fn enable_auto_swap(&self, max_slippage: u64) {
+        require!(max_slippage <= MAX_PERCENTAGE, "Invalid slippage");
+
+        let caller = self.blockchain().get_caller();
+        let caller_id = self.user_id().get_id_non_zero(&caller);
+        self.auto_swap_max_slippage(caller_id).set(max_slippage);
+    }
+
+    #[endpoint(disableAutoSwap)]
+    fn disable_auto_swap(&self) {
+        let caller = self.blockchain().get_caller();
+        let caller_id = self.user_id().get_id_non_zero(&caller);
+        self.auto_swap_max_slippage(caller_id).clear();
+    }
+
+    /// Swaps the first deposited token that can cover the missing balance of the required payment.
+    /// Nothing is swapped if auto-swap is disabled or no token can be swapped within the slippage limit.
+    /// Tokens kept off the user's whitelist for the service are not swapped, and neither are tokens whose pairs are not active.
+    /// The output of the swap is checked with getAmountOut beforehand, so the swap itself does not fail a batch.
+    fn cover_missing_balance_with_swap(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        required_payment: &EsdtTokenPayment,
+    ) {
+        let max_slippage_mapper = self.auto_swap_max_slippage(user_id);
+        let user_fees_mapper = self.user_deposited_fees(user_id);
+        if max_slippage_mapper.is_empty() || user_fees_mapper.is_empty() {
+            return;
+        }
+
+        let user_tokens = user_fees_mapper.get().into_payments();
+        let mut current_balance = BigUint::zero();
+        for user_token in user_tokens.iter() {
+            if user_token.token_identifier == required_payment.token_identifier {
+                current_balance = user_token.amount;
+                break;
+            }
+        }
+
+        if current_balance >= required_payment.amount {
+            return;
+        }
+
+        let missing_amount = &required_payment.amount - &current_balance;
+        let max_slippage = max_slippage_mapper.get();
+        let excluded_tokens_mapper = self.user_excluded_tokens(user_id);
+        let whitelist_mapper = self.service_token_whitelist(user_id, service_id);
+        for token_id in self
+            .get_user_payment_tokens_in_order(user_id, &user_tokens)
+            .iter()
+        {
+            if *token_id == required_payment.token_identifier
+                || excluded_tokens_mapper.contains(&token_id)
+                || (!whitelist_mapper.is_empty() && !whitelist_mapper.contains(&token_id))
+            {
+                continue;
+            }
+
+            let opt_hops = self.get_swap_hops(&token_id, &required_payment.token_identifier);
+            let hops = match opt_hops {
+                Some(hops) => hops,
+                None => continue,
+            };
+            if !self.are_pairs_active(&hops) {
+                continue;
+            }
+
+            let opt_input_payment = self.get_swap_input_payment(
+                &user_tokens,
+                &hops,
+                &token_id,
+                &required_payment.token_identifier,
+                &missing_amount,
+                max_slippage,
+            );
+            if let Some(input_payment) = opt_input_payment {
+                self.swap_user_tokens(user_id, &hops, input_payment, &missing_amount);
+
+                return;
+            }
+        }
+    }
+
+    /// Returns the payment to swap for at least amount_out of the output token, if the user has enough balance
+    /// and the pairs can provide it within the slippage limit.
+    /// The input amount is the safe price of amount_out, increased by the slippage.
+    fn get_swap_input_payment(
+        &self,
+        user_tokens: &ManagedVec<EsdtTokenPayment>,
+        hops: &ManagedVec<SwapHop<Self::Api>>,
+        input_token_id: &TokenIdentifier,
+        output_token_id: &TokenIdentifier,
+        amount_out: &BigUint,
+        max_slippage: u64,
+    ) -> Option<EsdtTokenPayment> {
+        let price_query_address = self.price_query_address().get();
+        let mut safe_price = EsdtTokenPayment::new(output_token_id.clone(), 0, amount_out.clone());
+        for hop in hops.iter().rev() {
+            safe_price = self
+                .pair_proxy(price_query_address.clone())
+                .get_safe_price_by_default_offset(hop.pair_address, safe_price)
+                .execute_on_dest_context();
+        }
+
+        if &safe_price.token_identifier != input_token_id {
+            return None;
+        }
+
+        let amount_in = safe_price.amount * (MAX_PERCENTAGE + max_slippage) / MAX_PERCENTAGE;
+        let has_balance = user_tokens.iter().any(|user_token| {
+            &user_token.token_identifier == input_token_id && user_token.amount >= amount_in
+        });
+        if !has_balance {
+            return None;
+        }
+
+        let mut expected_amount_out = amount_in.clone();
+        let mut token_in = input_token_id.clone();
+        for hop in hops.iter() {
+            expected_amount_out = self
+                .pair_proxy(hop.pair_address)
+                .get_amount_out(token_in, expected_amount_out)
+                .execute_on_dest_context();
+            token_in = hop.token_out;
+        }
+
+        if &expected_amount_out < amount_out {
+            return None;
+        }
+
+        Some(EsdtTokenPayment::new(input_token_id.clone(), 0, amount_in))
+    }
+
+    fn are_pairs_active(&self, hops: &ManagedVec<SwapHop<Self::Api>>) -> bool {
+        hops.iter()
+            .all(|hop| self.pair_state().get_from_address(&hop.pair_address) == PAIR_ACTIVE_STATE)
+    }
+
+    /// Swaps through the WEGLD pair of each token, as the default price route does
+    fn get_swap_hops(
+        &self,
+        input_token_id: &TokenIdentifier,
+        output_token_id: &TokenIdentifier,
+    ) -> Option<ManagedVec<SwapHop<Self::Api>>> {
+        let wegld_token_id = self.wegld_token_id().get();
+        let mut hops = ManagedVec::new();
+        if input_token_id != &wegld_token_id {
+            let pair_mapper = self.pair_address_for_token(input_token_id);
+            if pair_mapper.is_empty() {
+                return None;
+            }
+
+            hops.push(SwapHop {
+                pair_address: pair_mapper.get(),
+                token_out: wegld_token_id.clone(),
+            });
+        }
+
+        if output_token_id != &wegld_token_id {
+            let pair_mapper = self.pair_address_for_token(output_token_id);
+            if pair_mapper.is_empty() {
+                return None;
+            }
+
+            hops.push(SwapHop {
+                pair_address: pair_mapper.get(),
+                token_out: output_token_id.clone(),
+            });
+        }
+
+        Some(hops)
+    }
+
+    fn swap_user_tokens(
+        &self,
+        user_id: AddressId,
+        hops: &ManagedVec<SwapHop<Self::Api>>,
+        input_payment: EsdtTokenPayment,
+        amount_out_min: &BigUint,
+    ) {
+        let hops_len = hops.len();
+        let mut output_payment = input_payment.clone();
+        for (index, hop) in hops.iter().enumerate() {
+            let hop_amount_out_min = if index == hops_len - 1 {
+                amount_out_min.clone()
+            } else {
+                BigUint::from(1u32)
+            };
+
+            output_payment = self
+                .pair_proxy(hop.pair_address)
+                .swap_tokens_fixed_input(hop.token_out, hop_amount_out_min)
+                .with_esdt_transfer(output_payment)
+                .execute_on_dest_context();
+        }
+
+        self.user_deposited_fees(user_id).update(|user_fees| {
+            let deduct_result = user_fees.deduct_payment(&input_payment);
+            require!(deduct_result.is_ok(), "Insufficient balance for swap");
+
+            user_fees.add_payment(output_payment.clone());
+        });
+
+        self.emit_auto_swap_event(user_id, input_payment, output_payment);
+    }
+}
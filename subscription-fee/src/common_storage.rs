@@ -2,7 +2,17 @@ multiversx_sc::imports!();
 
 use common_structs::UniquePayments;
 
-use crate::{service::ServiceInfo, subtract_payments::Epoch};
+use crate::{
+    fees::PendingWithdrawal,
+    pair_actions::{PriceFeedNames, PriceSource},
+    service::{
+        EnergyDiscountTier, LastCharge, ServiceInfo, UserSubscription,
+        UserSubscriptionsBackfillProgress,
+    },
+    service_profile::{ServiceMetadata, ServiceProfile},
+    spending_limits::{PeriodSpending, SpendingCap},
+    subtract_payments::{Epoch, SubtractBatchProgress},
+};
 
 #[multiversx_sc::module]
 pub trait CommonStorageModule {
@@ -20,6 +30,21 @@ pub trait CommonStorageModule {
         user_id: AddressId,
     ) -> SingleValueMapper<UniquePayments<Self::Api>>;
 
+    #[view(getUserTokenPreferenceOrder)]
+    #[storage_mapper("userTokenPreferenceOrder")]
+    fn user_token_preference_order(
+        &self,
+        user_id: AddressId,
+    ) -> SingleValueMapper<ManagedVec<TokenIdentifier>>;
+
+    #[view(getUserExcludedTokens)]
+    #[storage_mapper("userExcludedTokens")]
+    fn user_excluded_tokens(&self, user_id: AddressId) -> UnorderedSetMapper<TokenIdentifier>;
+
+    #[view(getAutoSwapMaxSlippage)]
+    #[storage_mapper("autoSwapMaxSlippage")]
+    fn auto_swap_max_slippage(&self, user_id: AddressId) -> SingleValueMapper<u64>;
+
     #[view(getMinStableTokenDepositValue)]
     #[storage_mapper("minStableTokenDepositValue")]
     fn min_stable_token_deposit_value(&self) -> SingleValueMapper<BigUint>;
@@ -35,6 +60,45 @@ pub trait CommonStorageModule {
     #[storage_mapper("serviceId")]
     fn service_id(&self) -> AddressToIdMapper<Self::Api>;
 
+    // services that moved to a new address keep the ID from the serviceId mapper, which still holds their original address
+    #[storage_mapper("movedServiceId")]
+    fn moved_service_id(&self, service_address: &ManagedAddress) -> SingleValueMapper<AddressId>;
+
+    #[storage_mapper("movedServiceAddress")]
+    fn moved_service_address(&self, service_id: AddressId) -> SingleValueMapper<ManagedAddress>;
+
+    #[storage_mapper("serviceProfile")]
+    fn service_profile(
+        &self,
+        service_id: AddressId,
+    ) -> SingleValueMapper<ServiceProfile<Self::Api>>;
+
+    #[view(getServiceOptionLabel)]
+    #[storage_mapper("serviceOptionLabel")]
+    fn service_option_label(
+        &self,
+        service_id: AddressId,
+        service_index: usize,
+    ) -> SingleValueMapper<ManagedBuffer>;
+
+    #[view(getPendingServiceMetadata)]
+    #[storage_mapper("pendingServiceMetadata")]
+    fn pending_service_metadata(
+        &self,
+        service_address: &ManagedAddress,
+    ) -> SingleValueMapper<ServiceMetadata<Self::Api>>;
+
+    // one label for each pending option, empty if not provided
+    #[storage_mapper("pendingServiceOptionLabels")]
+    fn pending_service_option_labels(
+        &self,
+        service_address: &ManagedAddress,
+    ) -> SingleValueMapper<ManagedVec<ManagedBuffer>>;
+
+    #[view(getPendingServiceOwner)]
+    #[storage_mapper("pendingServiceOwner")]
+    fn pending_service_owner(&self, service_id: AddressId) -> SingleValueMapper<ManagedAddress>;
+
     #[view(getPendingServices)]
     #[storage_mapper("pendingServices")]
     fn pending_services(&self) -> UnorderedSetMapper<ManagedAddress>;
@@ -53,6 +117,39 @@ pub trait CommonStorageModule {
         service_id: AddressId,
     ) -> SingleValueMapper<ManagedVec<ServiceInfo<Self::Api>>>;
 
+    #[view(getPendingServiceOptionUpdate)]
+    #[storage_mapper("pendingServiceOptionUpdate")]
+    fn pending_service_option_update(
+        &self,
+        service_id: AddressId,
+        service_index: usize,
+    ) -> SingleValueMapper<ServiceInfo<Self::Api>>;
+
+    #[view(getServiceOptionVersion)]
+    #[storage_mapper("serviceOptionVersion")]
+    fn service_option_version(
+        &self,
+        service_id: AddressId,
+        service_index: usize,
+    ) -> SingleValueMapper<u32>;
+
+    #[view(isServiceOptionDeprecated)]
+    #[storage_mapper("deprecatedServiceOption")]
+    fn deprecated_service_option(
+        &self,
+        service_id: AddressId,
+        service_index: usize,
+    ) -> SingleValueMapper<bool>;
+
+    #[view(getUserOptionVersion)]
+    #[storage_mapper("userOptionVersion")]
+    fn user_option_version(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        service_index: usize,
+    ) -> SingleValueMapper<u32>;
+
     #[view(getSubscribedUsers)]
     #[storage_mapper("subscribedUsers")]
     fn subscribed_users(
@@ -61,12 +158,254 @@ pub trait CommonStorageModule {
         service_index: usize,
     ) -> UnorderedSetMapper<AddressId>;
 
+    #[storage_mapper("userSubscriptions")]
+    fn user_subscriptions(&self, user_id: AddressId) -> UnorderedSetMapper<UserSubscription>;
+
+    #[storage_mapper("userSubscriptionsBackfillProgress")]
+    fn user_subscriptions_backfill_progress(
+        &self,
+    ) -> SingleValueMapper<UserSubscriptionsBackfillProgress>;
+
+    #[view(getProratedRefundsEnabled)]
+    #[storage_mapper("proratedRefundsEnabled")]
+    fn prorated_refunds_enabled(
+        &self,
+        service_id: AddressId,
+        service_index: usize,
+    ) -> SingleValueMapper<bool>;
+
+    #[view(isCycleEscrowEnabled)]
+    #[storage_mapper("cycleEscrowEnabled")]
+    fn cycle_escrow_enabled(
+        &self,
+        service_id: AddressId,
+        service_index: usize,
+    ) -> SingleValueMapper<bool>;
+
+    #[view(getCycleEscrow)]
+    #[storage_mapper("cycleEscrow")]
+    fn cycle_escrow(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        service_index: usize,
+    ) -> SingleValueMapper<EsdtTokenPayment>;
+
+    #[view(isWithdrawalReserveEnabled)]
+    #[storage_mapper("withdrawalReserveEnabled")]
+    fn withdrawal_reserve_enabled(
+        &self,
+        service_id: AddressId,
+        service_index: usize,
+    ) -> SingleValueMapper<bool>;
+
+    #[view(getWithdrawalDelayEpochs)]
+    #[storage_mapper("withdrawalDelayEpochs")]
+    fn withdrawal_delay_epochs(&self) -> SingleValueMapper<Epoch>;
+
+    #[view(getPendingWithdrawal)]
+    #[storage_mapper("pendingWithdrawal")]
+    fn pending_withdrawal(
+        &self,
+        user_id: AddressId,
+    ) -> SingleValueMapper<PendingWithdrawal<Self::Api>>;
+
+    #[view(getServiceTrialEpochs)]
+    #[storage_mapper("serviceTrialEpochs")]
+    fn service_trial_epochs(
+        &self,
+        service_id: AddressId,
+        service_index: usize,
+    ) -> SingleValueMapper<Epoch>;
+
+    #[view(hasUsedTrial)]
+    #[storage_mapper("usedTrial")]
+    fn used_trial(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        service_index: usize,
+    ) -> SingleValueMapper<bool>;
+
+    #[view(getEnergyDiscountTiers)]
+    #[storage_mapper("energyDiscountTiers")]
+    fn energy_discount_tiers(
+        &self,
+        service_id: AddressId,
+        service_index: usize,
+    ) -> SingleValueMapper<ManagedVec<EnergyDiscountTier<Self::Api>>>;
+
+    #[view(getLastCharge)]
+    #[storage_mapper("lastCharge")]
+    fn last_charge(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        service_index: usize,
+    ) -> SingleValueMapper<LastCharge<Self::Api>>;
+
+    #[view(getPendingRefunds)]
+    #[storage_mapper("pendingRefunds")]
+    fn pending_refunds(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+    ) -> SingleValueMapper<UniquePayments<Self::Api>>;
+
+    #[view(getUsersWithPendingRefunds)]
+    #[storage_mapper("usersWithPendingRefunds")]
+    fn users_with_pending_refunds(&self, service_id: AddressId) -> UnorderedSetMapper<AddressId>;
+
+    #[view(getServiceGracePeriod)]
+    #[storage_mapper("serviceGracePeriod")]
+    fn service_grace_period(&self, service_id: AddressId) -> SingleValueMapper<Epoch>;
+
+    #[view(getFailedChargeAttempts)]
+    #[storage_mapper("failedChargeAttempts")]
+    fn failed_charge_attempts(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        service_index: usize,
+    ) -> SingleValueMapper<usize>;
+
+    #[view(getPastDueEpoch)]
+    #[storage_mapper("pastDueEpoch")]
+    fn past_due_epoch(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        service_index: usize,
+    ) -> SingleValueMapper<Epoch>;
+
+    #[view(getSubtractBatchProgress)]
+    #[storage_mapper("subtractBatchProgress")]
+    fn subtract_batch_progress(
+        &self,
+        service_id: AddressId,
+    ) -> SingleValueMapper<SubtractBatchProgress<Self::Api>>;
+
+    #[view(isAutomaticBillingEnabled)]
+    #[storage_mapper("automaticBillingEnabled")]
+    fn automatic_billing_enabled(&self, service_id: AddressId) -> SingleValueMapper<bool>;
+
+    #[storage_mapper("dueSubscriptionsCursor")]
+    fn due_subscriptions_cursor(
+        &self,
+        service_id: AddressId,
+        service_index: usize,
+    ) -> SingleValueMapper<usize>;
+
     #[storage_mapper("pairAddressForToken")]
     fn pair_address_for_token(
         &self,
         token_id: &TokenIdentifier,
     ) -> SingleValueMapper<ManagedAddress<Self::Api>>;
 
+    #[view(getTokenPriceRoute)]
+    #[storage_mapper("tokenPriceRoute")]
+    fn token_price_route(
+        &self,
+        token_id: &TokenIdentifier,
+    ) -> SingleValueMapper<ManagedVec<ManagedAddress>>;
+
+    #[view(getTokenPriceSource)]
+    #[storage_mapper("tokenPriceSource")]
+    fn token_price_source(
+        &self,
+        token_id: &TokenIdentifier,
+    ) -> SingleValueMapper<PriceSource<Self::Api>>;
+
+    #[view(getTokenPriceFeedNames)]
+    #[storage_mapper("tokenPriceFeedNames")]
+    fn token_price_feed_names(
+        &self,
+        token_id: &TokenIdentifier,
+    ) -> SingleValueMapper<PriceFeedNames<Self::Api>>;
+
+    #[view(getTokenDecimals)]
+    #[storage_mapper("tokenDecimals")]
+    fn token_decimals(&self, token_id: &TokenIdentifier) -> SingleValueMapper<u32>;
+
+    #[view(getPriceAggregatorAddress)]
+    #[storage_mapper("priceAggregatorAddress")]
+    fn price_aggregator_address(&self) -> SingleValueMapper<ManagedAddress>;
+
+    #[view(getMaxPriceStaleness)]
+    #[storage_mapper("maxPriceStaleness")]
+    fn max_price_staleness(&self) -> SingleValueMapper<u64>;
+
+    #[view(getMaxPriceDeviation)]
+    #[storage_mapper("maxPriceDeviation")]
+    fn max_price_deviation(&self) -> SingleValueMapper<u64>;
+
+    #[view(isRevenuePullModeEnabled)]
+    #[storage_mapper("revenuePullMode")]
+    fn revenue_pull_mode(&self, service_id: AddressId) -> SingleValueMapper<bool>;
+
+    #[view(getServiceRevenue)]
+    #[storage_mapper("serviceRevenue")]
+    fn service_revenue(
+        &self,
+        service_id: AddressId,
+    ) -> SingleValueMapper<UniquePayments<Self::Api>>;
+
+    #[view(getUnregisteredServiceRevenueOwner)]
+    #[storage_mapper("unregisteredServiceRevenueOwner")]
+    fn unregistered_service_revenue_owner(
+        &self,
+        service_id: AddressId,
+    ) -> SingleValueMapper<ManagedAddress>;
+
+    #[view(getServiceLifetimeRevenue)]
+    #[storage_mapper("serviceLifetimeRevenue")]
+    fn service_lifetime_revenue(
+        &self,
+        service_id: AddressId,
+    ) -> SingleValueMapper<UniquePayments<Self::Api>>;
+
+    #[view(getProtocolFeePercentage)]
+    #[storage_mapper("protocolFeePercentage")]
+    fn protocol_fee_percentage(&self) -> SingleValueMapper<u64>;
+
+    #[view(getProtocolFees)]
+    #[storage_mapper("protocolFees")]
+    fn protocol_fees(&self) -> SingleValueMapper<UniquePayments<Self::Api>>;
+
+    #[view(getProtocolFeesClaimAddress)]
+    #[storage_mapper("protocolFeesClaimAddress")]
+    fn protocol_fees_claim_address(&self) -> SingleValueMapper<ManagedAddress>;
+
+    #[view(getSpendingCap)]
+    #[storage_mapper("spendingCap")]
+    fn spending_cap(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        token_id: &TokenIdentifier,
+    ) -> SingleValueMapper<SpendingCap<Self::Api>>;
+
+    #[view(getSpendingInPeriod)]
+    #[storage_mapper("spendingInPeriod")]
+    fn spending_in_period(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        token_id: &TokenIdentifier,
+    ) -> SingleValueMapper<PeriodSpending<Self::Api>>;
+
+    #[view(getServiceTokenWhitelist)]
+    #[storage_mapper("serviceTokenWhitelist")]
+    fn service_token_whitelist(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+    ) -> UnorderedSetMapper<TokenIdentifier>;
+
+    #[view(getEgldWrapperAddress)]
+    #[storage_mapper("egldWrapperAddress")]
+    fn egld_wrapper_address(&self) -> SingleValueMapper<ManagedAddress>;
+
     #[storage_mapper("stableTokenId")]
     fn stable_token_id(&self) -> SingleValueMapper<TokenIdentifier>;
 
@@ -75,4 +414,15 @@ pub trait CommonStorageModule {
 
     #[storage_mapper("priceQueryAddress")]
     fn price_query_address(&self) -> SingleValueMapper<ManagedAddress>;
+
+    // used for external storage read
+    #[storage_mapper("first_token_id")]
+    fn first_token_id(&self) -> SingleValueMapper<TokenIdentifier>;
+
+    #[storage_mapper("second_token_id")]
+    fn second_token_id(&self) -> SingleValueMapper<TokenIdentifier>;
+
+    // the pair state enum, encoded as its index
+    #[storage_mapper("state")]
+    fn pair_state(&self) -> SingleValueMapper<u8>;
 }
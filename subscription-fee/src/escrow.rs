@@ -0,0 +1,198 @@
+multiversx_sc::imports!();
+
+use common_structs::UniquePayments;
+
+use crate::service::ServiceInfo;
+
+#[multiversx_sc::module]
+pub trait EscrowModule:
+    crate::fees::FeesModule
+    + crate::pair_actions::PairActionsModule
+    + crate::common_storage::CommonStorageModule
+    + crate::events::EventsModule
+    + multiversx_sc_modules::pause::PauseModule
+    + crate::service_profile::ServiceProfileModule
+{
+    /// When enabled, the next cycle of each subscriber of the given service option is moved from their deposits into escrow,
+    /// when they subscribe and after each charge. The service is then paid from the escrow, so other services cannot use those funds.
+    #[endpoint(setCycleEscrow)]
+    fn set_cycle_escrow(&self, service_index: usize, enabled: bool) {
+        let service_address = self.blockchain().get_caller();
+        let service_id = self.get_service_id_non_zero(&service_address);
+        let service_options = self.service_info(service_id).get();
+        require!(
+            service_index < service_options.len(),
+            "Invalid service index"
+        );
+
+        if enabled {
+            self.cycle_escrow_enabled(service_id, service_index)
+                .set(true);
+        } else {
+            self.cycle_escrow_enabled(service_id, service_index).clear();
+        }
+    }
+
+    /// Returns the free balance of the user, followed by the balance held in escrow for their subscriptions
+    #[view(getUserBalances)]
+    fn get_user_balances(
+        &self,
+        user_address: ManagedAddress,
+    ) -> MultiValue2<ManagedVec<EsdtTokenPayment>, ManagedVec<EsdtTokenPayment>> {
+        let user_id = self.user_id().get_id(&user_address);
+        if user_id == NULL_ID {
+            return (ManagedVec::new(), ManagedVec::new()).into();
+        }
+
+        let user_fees_mapper = self.user_deposited_fees(user_id);
+        let free_balance = if user_fees_mapper.is_empty() {
+            ManagedVec::new()
+        } else {
+            user_fees_mapper.get().into_payments()
+        };
+
+        let mut reserved_balance = UniquePayments::new();
+        for subscription in self.user_subscriptions(user_id).iter() {
+            let escrow_mapper =
+                self.cycle_escrow(user_id, subscription.service_id, subscription.service_index);
+            if !escrow_mapper.is_empty() {
+                reserved_balance.add_payment(escrow_mapper.get());
+            }
+        }
+
+        (free_balance, reserved_balance.into_payments()).into()
+    }
+
+    /// Moves the next cycle into escrow, if the service option has it enabled and the user's free balance covers it.
+    /// Otherwise, the next charge is taken from the free balance, as usual.
+    fn try_escrow_next_cycle(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        service_index: usize,
+    ) {
+        let escrow_mapper = self.cycle_escrow(user_id, service_id, service_index);
+        if !self.cycle_escrow_enabled(service_id, service_index).get() || !escrow_mapper.is_empty()
+        {
+            return;
+        }
+
+        let user_fees_mapper = self.user_deposited_fees(user_id);
+        let service_options = self.service_info(service_id).get();
+        if user_fees_mapper.is_empty() || service_index >= service_options.len() {
+            return;
+        }
+
+        let opt_payment = self.get_cycle_payment(user_id, service_options.get(service_index));
+        let payment = match opt_payment {
+            Some(payment) => payment,
+            None => return,
+        };
+
+        let deduct_result = user_fees_mapper.update(|user_fees| user_fees.deduct_payment(&payment));
+        if deduct_result.is_ok() {
+            escrow_mapper.set(&payment);
+            self.emit_escrow_event(user_id, service_id, service_index, payment);
+        }
+    }
+
+    /// Services that accept any token are escrowed in the first token the user would be charged with
+    fn get_cycle_payment(
+        &self,
+        user_id: AddressId,
+        service_info: ServiceInfo<Self::Api>,
+    ) -> Option<EsdtTokenPayment> {
+        if let Some(token_id) = service_info.opt_payment_token {
+            if !service_info.payment_in_stable {
+                return Some(EsdtTokenPayment::new(token_id, 0, service_info.amount));
+            }
+
+            let query_result = self.get_worth_of_price(&token_id, service_info.amount);
+            return query_result
+                .ok()
+                .map(|amount| EsdtTokenPayment::new(token_id, 0, amount));
+        }
+
+        let user_fees_mapper = self.user_deposited_fees(user_id);
+        if user_fees_mapper.is_empty() {
+            return None;
+        }
+
+        let user_tokens = user_fees_mapper.get().into_payments();
+        let excluded_tokens_mapper = self.user_excluded_tokens(user_id);
+        for token_id in self
+            .get_user_payment_tokens_in_order(user_id, &user_tokens)
+            .iter()
+        {
+            if excluded_tokens_mapper.contains(&token_id) {
+                continue;
+            }
+
+            let query_result = self.get_worth_of_price(&token_id, service_info.amount.clone());
+            let amount = match query_result {
+                Result::Ok(amount) => amount,
+                Result::Err(()) => continue,
+            };
+
+            let has_balance = user_tokens.iter().any(|user_token| {
+                user_token.token_identifier == *token_id && user_token.amount >= amount
+            });
+            if has_balance {
+                return Some(EsdtTokenPayment::new((*token_id).clone(), 0, amount));
+            }
+        }
+
+        None
+    }
+
+    /// The escrow holds the cycle as priced when it was reserved. It is released if the service option
+    /// was updated since, or no longer accepts the escrowed token, so the user is charged the current terms.
+    fn release_outdated_escrow(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        service_index: usize,
+        service_info: &ServiceInfo<Self::Api>,
+    ) {
+        let escrow_mapper = self.cycle_escrow(user_id, service_id, service_index);
+        if escrow_mapper.is_empty() {
+            return;
+        }
+
+        let option_version = self.service_option_version(service_id, service_index).get();
+        let user_version = self
+            .user_option_version(user_id, service_id, service_index)
+            .get();
+        let is_token_accepted = match &service_info.opt_payment_token {
+            Some(token_id) => escrow_mapper.get().token_identifier == *token_id,
+            None => true,
+        };
+        if user_version == option_version && is_token_accepted {
+            return;
+        }
+
+        self.release_escrow(user_id, service_id, service_index);
+    }
+
+    /// Moves the escrowed payment back to the user's free balance
+    fn release_escrow(&self, user_id: AddressId, service_id: AddressId, service_index: usize) {
+        let escrow_mapper = self.cycle_escrow(user_id, service_id, service_index);
+        if escrow_mapper.is_empty() {
+            return;
+        }
+
+        let payment = escrow_mapper.take();
+        self.return_escrowed_payment(user_id, service_id, service_index, payment);
+    }
+
+    fn return_escrowed_payment(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        service_index: usize,
+        payment: EsdtTokenPayment,
+    ) {
+        self.add_user_payment(payment.clone(), self.user_deposited_fees(user_id));
+        self.emit_release_escrow_event(user_id, service_id, service_index, payment);
+    }
+}
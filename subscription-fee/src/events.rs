@@ -0,0 +1,410 @@
+multiversx_sc::imports!();
+
+use crate::{service::ServiceInfo, subtract_payments::Epoch};
+
+#[multiversx_sc::module]
+pub trait EventsModule {
+    fn emit_deposit_event(&self, user_id: AddressId, payment: EsdtTokenPayment) {
+        let epoch = self.blockchain().get_block_epoch();
+        self.deposit_event(user_id, epoch, payment)
+    }
+
+    fn emit_withdraw_event(&self, user_id: AddressId, payments: ManagedVec<EsdtTokenPayment>) {
+        let epoch = self.blockchain().get_block_epoch();
+        self.withdraw_event(user_id, epoch, payments)
+    }
+
+    fn emit_register_service_event(
+        &self,
+        service_address: ManagedAddress,
+        service_options: ManagedVec<ServiceInfo<Self::Api>>,
+    ) {
+        let epoch = self.blockchain().get_block_epoch();
+        self.register_service_event(service_address, epoch, service_options)
+    }
+
+    fn emit_approve_service_event(&self, service_id: AddressId, service_address: ManagedAddress) {
+        let epoch = self.blockchain().get_block_epoch();
+        self.approve_service_event(service_id, epoch, service_address)
+    }
+
+    fn emit_service_ownership_transferred_event(
+        &self,
+        service_id: AddressId,
+        previous_owner: ManagedAddress,
+        new_owner: ManagedAddress,
+    ) {
+        let epoch = self.blockchain().get_block_epoch();
+        self.service_ownership_transferred_event(service_id, previous_owner, epoch, new_owner)
+    }
+
+    fn emit_service_address_changed_event(
+        &self,
+        service_id: AddressId,
+        previous_address: ManagedAddress,
+        new_address: ManagedAddress,
+    ) {
+        let epoch = self.blockchain().get_block_epoch();
+        self.service_address_changed_event(service_id, previous_address, epoch, new_address)
+    }
+
+    fn emit_subscribe_event(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        service_index: usize,
+    ) {
+        let epoch = self.blockchain().get_block_epoch();
+        self.subscribe_event(user_id, service_id, service_index, epoch)
+    }
+
+    fn emit_unsubscribe_event(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        service_index: usize,
+    ) {
+        let epoch = self.blockchain().get_block_epoch();
+        self.unsubscribe_event(user_id, service_id, service_index, epoch)
+    }
+
+    fn emit_subtract_payment_event(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        service_index: usize,
+        payment: EsdtTokenPayment,
+    ) {
+        let epoch = self.blockchain().get_block_epoch();
+        self.subtract_payment_event(user_id, service_id, service_index, epoch, payment)
+    }
+
+    fn emit_protocol_fee_event(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        service_index: usize,
+        fee_payment: EsdtTokenPayment,
+    ) {
+        let epoch = self.blockchain().get_block_epoch();
+        self.protocol_fee_event(user_id, service_id, service_index, epoch, fee_payment)
+    }
+
+    fn emit_subscription_past_due_event(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        service_index: usize,
+        failed_attempts: usize,
+    ) {
+        let epoch = self.blockchain().get_block_epoch();
+        self.subscription_past_due_event(user_id, service_id, service_index, epoch, failed_attempts)
+    }
+
+    fn emit_subscription_recovered_event(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        service_index: usize,
+    ) {
+        let epoch = self.blockchain().get_block_epoch();
+        self.subscription_recovered_event(user_id, service_id, service_index, epoch)
+    }
+
+    fn emit_auto_unsubscribe_event(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        service_index: usize,
+        failed_attempts: usize,
+    ) {
+        let epoch = self.blockchain().get_block_epoch();
+        self.auto_unsubscribe_event(user_id, service_id, service_index, epoch, failed_attempts)
+    }
+
+    fn emit_service_option_updated_event(
+        &self,
+        service_id: AddressId,
+        service_index: usize,
+        version: u32,
+        service_info: ServiceInfo<Self::Api>,
+    ) {
+        let epoch = self.blockchain().get_block_epoch();
+        self.service_option_updated_event(service_id, service_index, version, epoch, service_info)
+    }
+
+    fn emit_service_option_deprecated_event(&self, service_id: AddressId, service_index: usize) {
+        let epoch = self.blockchain().get_block_epoch();
+        self.service_option_deprecated_event(service_id, service_index, epoch)
+    }
+
+    fn emit_subscription_migrated_event(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        service_index: usize,
+        from_version: u32,
+        to_version: u32,
+    ) {
+        let epoch = self.blockchain().get_block_epoch();
+        self.subscription_migrated_event(
+            user_id,
+            service_id,
+            service_index,
+            from_version,
+            epoch,
+            to_version,
+        )
+    }
+
+    fn emit_auto_swap_event(
+        &self,
+        user_id: AddressId,
+        input_payment: EsdtTokenPayment,
+        output_payment: EsdtTokenPayment,
+    ) {
+        let epoch = self.blockchain().get_block_epoch();
+        self.auto_swap_event(user_id, epoch, input_payment, output_payment)
+    }
+
+    fn emit_claim_service_revenue_event(
+        &self,
+        service_id: AddressId,
+        destination: ManagedAddress,
+        payments: ManagedVec<EsdtTokenPayment>,
+    ) {
+        let epoch = self.blockchain().get_block_epoch();
+        self.claim_service_revenue_event(service_id, destination, epoch, payments)
+    }
+
+    fn emit_refund_event(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        payment: EsdtTokenPayment,
+    ) {
+        let epoch = self.blockchain().get_block_epoch();
+        self.refund_event(user_id, service_id, epoch, payment)
+    }
+
+    fn emit_escrow_event(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        service_index: usize,
+        payment: EsdtTokenPayment,
+    ) {
+        let epoch = self.blockchain().get_block_epoch();
+        self.escrow_event(user_id, service_id, service_index, epoch, payment)
+    }
+
+    fn emit_release_escrow_event(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        service_index: usize,
+        payment: EsdtTokenPayment,
+    ) {
+        let epoch = self.blockchain().get_block_epoch();
+        self.release_escrow_event(user_id, service_id, service_index, epoch, payment)
+    }
+
+    #[event("depositEvent")]
+    fn deposit_event(
+        &self,
+        #[indexed] user_id: AddressId,
+        #[indexed] epoch: Epoch,
+        payment: EsdtTokenPayment,
+    );
+
+    #[event("withdrawEvent")]
+    fn withdraw_event(
+        &self,
+        #[indexed] user_id: AddressId,
+        #[indexed] epoch: Epoch,
+        payments: ManagedVec<EsdtTokenPayment>,
+    );
+
+    /// Emitted for both new registrations and extra services, with the options that were added
+    #[event("registerServiceEvent")]
+    fn register_service_event(
+        &self,
+        #[indexed] service_address: ManagedAddress,
+        #[indexed] epoch: Epoch,
+        service_options: ManagedVec<ServiceInfo<Self::Api>>,
+    );
+
+    #[event("approveServiceEvent")]
+    fn approve_service_event(
+        &self,
+        #[indexed] service_id: AddressId,
+        #[indexed] epoch: Epoch,
+        service_address: ManagedAddress,
+    );
+
+    #[event("serviceOwnershipTransferredEvent")]
+    fn service_ownership_transferred_event(
+        &self,
+        #[indexed] service_id: AddressId,
+        #[indexed] previous_owner: ManagedAddress,
+        #[indexed] epoch: Epoch,
+        new_owner: ManagedAddress,
+    );
+
+    #[event("serviceAddressChangedEvent")]
+    fn service_address_changed_event(
+        &self,
+        #[indexed] service_id: AddressId,
+        #[indexed] previous_address: ManagedAddress,
+        #[indexed] epoch: Epoch,
+        new_address: ManagedAddress,
+    );
+
+    #[event("subscribeEvent")]
+    fn subscribe_event(
+        &self,
+        #[indexed] user_id: AddressId,
+        #[indexed] service_id: AddressId,
+        #[indexed] service_index: usize,
+        #[indexed] epoch: Epoch,
+    );
+
+    #[event("unsubscribeEvent")]
+    fn unsubscribe_event(
+        &self,
+        #[indexed] user_id: AddressId,
+        #[indexed] service_id: AddressId,
+        #[indexed] service_index: usize,
+        #[indexed] epoch: Epoch,
+    );
+
+    #[event("subtractPaymentEvent")]
+    fn subtract_payment_event(
+        &self,
+        #[indexed] user_id: AddressId,
+        #[indexed] service_id: AddressId,
+        #[indexed] service_index: usize,
+        #[indexed] epoch: Epoch,
+        payment: EsdtTokenPayment,
+    );
+
+    #[event("protocolFeeEvent")]
+    fn protocol_fee_event(
+        &self,
+        #[indexed] user_id: AddressId,
+        #[indexed] service_id: AddressId,
+        #[indexed] service_index: usize,
+        #[indexed] epoch: Epoch,
+        fee_payment: EsdtTokenPayment,
+    );
+
+    #[event("subscriptionPastDueEvent")]
+    fn subscription_past_due_event(
+        &self,
+        #[indexed] user_id: AddressId,
+        #[indexed] service_id: AddressId,
+        #[indexed] service_index: usize,
+        #[indexed] epoch: Epoch,
+        failed_attempts: usize,
+    );
+
+    #[event("subscriptionRecoveredEvent")]
+    fn subscription_recovered_event(
+        &self,
+        #[indexed] user_id: AddressId,
+        #[indexed] service_id: AddressId,
+        #[indexed] service_index: usize,
+        #[indexed] epoch: Epoch,
+    );
+
+    #[event("autoUnsubscribeEvent")]
+    fn auto_unsubscribe_event(
+        &self,
+        #[indexed] user_id: AddressId,
+        #[indexed] service_id: AddressId,
+        #[indexed] service_index: usize,
+        #[indexed] epoch: Epoch,
+        failed_attempts: usize,
+    );
+
+    #[event("serviceOptionUpdatedEvent")]
+    fn service_option_updated_event(
+        &self,
+        #[indexed] service_id: AddressId,
+        #[indexed] service_index: usize,
+        #[indexed] version: u32,
+        #[indexed] epoch: Epoch,
+        service_info: ServiceInfo<Self::Api>,
+    );
+
+    #[event("serviceOptionDeprecatedEvent")]
+    fn service_option_deprecated_event(
+        &self,
+        #[indexed] service_id: AddressId,
+        #[indexed] service_index: usize,
+        #[indexed] epoch: Epoch,
+    );
+
+    /// Emitted on the first charge of a subscriber after the service option was updated
+    #[event("subscriptionMigratedEvent")]
+    fn subscription_migrated_event(
+        &self,
+        #[indexed] user_id: AddressId,
+        #[indexed] service_id: AddressId,
+        #[indexed] service_index: usize,
+        #[indexed] from_version: u32,
+        #[indexed] epoch: Epoch,
+        to_version: u32,
+    );
+
+    #[event("claimServiceRevenueEvent")]
+    fn claim_service_revenue_event(
+        &self,
+        #[indexed] service_id: AddressId,
+        #[indexed] destination: ManagedAddress,
+        #[indexed] epoch: Epoch,
+        payments: ManagedVec<EsdtTokenPayment>,
+    );
+
+    /// Emitted when deposited tokens are swapped to cover a charge in another token
+    #[event("autoSwapEvent")]
+    fn auto_swap_event(
+        &self,
+        #[indexed] user_id: AddressId,
+        #[indexed] epoch: Epoch,
+        #[indexed] input_payment: EsdtTokenPayment,
+        output_payment: EsdtTokenPayment,
+    );
+
+    /// Emitted for each refund paid by a service, which is added to the user's deposits
+    #[event("refundEvent")]
+    fn refund_event(
+        &self,
+        #[indexed] user_id: AddressId,
+        #[indexed] service_id: AddressId,
+        #[indexed] epoch: Epoch,
+        payment: EsdtTokenPayment,
+    );
+
+    /// The payment was moved from the user's deposits into the escrow of the service option
+    #[event("escrowEvent")]
+    fn escrow_event(
+        &self,
+        #[indexed] user_id: AddressId,
+        #[indexed] service_id: AddressId,
+        #[indexed] service_index: usize,
+        #[indexed] epoch: Epoch,
+        payment: EsdtTokenPayment,
+    );
+
+    /// The payment was moved from the escrow of the service option back to the user's deposits
+    #[event("releaseEscrowEvent")]
+    fn release_escrow_event(
+        &self,
+        #[indexed] user_id: AddressId,
+        #[indexed] service_id: AddressId,
+        #[indexed] service_index: usize,
+        #[indexed] epoch: Epoch,
+        payment: EsdtTokenPayment,
+    );
+}
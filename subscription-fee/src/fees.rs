@@ -1,14 +1,37 @@
 multiversx_sc::imports!();
+multiversx_sc::derive_imports!();
 
 use common_structs::UniquePayments;
 
 use crate::common_storage;
-use crate::pair_actions;
+use crate::events;
+use crate::pair_actions::{self, MAX_PERCENTAGE};
 use crate::service::MAX_USER_DEPOSITS;
+use crate::subtract_payments::Epoch;
+
+#[derive(TypeAbi, TopEncode, TopDecode, NestedEncode, NestedDecode)]
+pub struct PendingWithdrawal<M: ManagedTypeApi> {
+    pub payments: ManagedVec<M, EsdtTokenPayment<M>>,
+    pub unlock_epoch: Epoch,
+}
+
+pub mod egld_wrapper_proxy {
+    multiversx_sc::imports!();
+
+    #[multiversx_sc::proxy]
+    pub trait EgldWrapperProxy {
+        #[payable("EGLD")]
+        #[endpoint(wrapEgld)]
+        fn wrap_egld(&self) -> EsdtTokenPayment;
+    }
+}
 
 #[multiversx_sc::module]
 pub trait FeesModule:
-    pair_actions::PairActionsModule + common_storage::CommonStorageModule
+    pair_actions::PairActionsModule
+    + common_storage::CommonStorageModule
+    + events::EventsModule
+    + multiversx_sc_modules::pause::PauseModule
 {
     #[only_owner]
     #[endpoint(addAcceptedFeesTokens)]
@@ -31,35 +54,76 @@ pub trait FeesModule:
         }
     }
 
-    #[payable("*")]
-    #[endpoint]
-    fn deposit(&self) {
-        let payment = self.call_value().single_esdt();
-        require!(payment.amount > 0, "No payment");
-        require!(payment.token_nonce == 0, "Can deposit only fungible tokens");
+    /// Percentage of each charge kept by the protocol, where 10,000 = 100%. It must be lower than 100%, so services are always paid.
+    #[only_owner]
+    #[endpoint(setProtocolFeePercentage)]
+    fn set_protocol_fee_percentage(&self, protocol_fee_percentage: u64) {
         require!(
-            self.accepted_fees_tokens()
-                .contains(&payment.token_identifier),
-            "Invalid payment token"
+            protocol_fee_percentage < MAX_PERCENTAGE,
+            "Invalid protocol fee percentage"
         );
 
-        let min_stable_token_deposit_value = self.min_stable_token_deposit_value().get();
-        let min_payment_value_result =
-            self.get_worth_of_price(&payment.token_identifier, min_stable_token_deposit_value);
+        self.protocol_fee_percentage().set(protocol_fee_percentage);
+    }
+
+    /// EGLD deposits are wrapped into WEGLD through this contract
+    #[only_owner]
+    #[endpoint(setEgldWrapperAddress)]
+    fn set_egld_wrapper_address(&self, wrapper_address: ManagedAddress) {
         require!(
-            min_payment_value_result.is_ok(),
-            "Could not get payment value"
+            self.blockchain().is_smart_contract(&wrapper_address),
+            "Invalid wrapper address"
         );
-        let min_payment_value = unsafe { min_payment_value_result.unwrap_unchecked() };
 
+        self.egld_wrapper_address().set(wrapper_address);
+    }
+
+    /// Number of epochs a withdrawal request waits before it can be claimed
+    #[only_owner]
+    #[endpoint(setWithdrawalDelay)]
+    fn set_withdrawal_delay(&self, delay_epochs: Epoch) {
+        require!(delay_epochs > 0, "Invalid withdrawal delay");
+
+        self.withdrawal_delay_epochs().set(delay_epochs);
+    }
+
+    #[only_owner]
+    #[endpoint(setProtocolFeesClaimAddress)]
+    fn set_protocol_fees_claim_address(&self, claim_address: ManagedAddress) {
+        self.protocol_fees_claim_address().set(claim_address);
+    }
+
+    #[only_owner]
+    #[endpoint(claimProtocolFees)]
+    fn claim_protocol_fees(&self) -> ManagedVec<EsdtTokenPayment> {
+        let claim_address_mapper = self.protocol_fees_claim_address();
         require!(
-            payment.amount >= min_payment_value,
-            "Payment value is lesser than the minimum accepted"
+            !claim_address_mapper.is_empty(),
+            "Protocol fees claim address not set"
         );
 
+        let protocol_fees_mapper = self.protocol_fees();
+        if protocol_fees_mapper.is_empty() {
+            return ManagedVec::new();
+        }
+
+        let payments = protocol_fees_mapper.take().into_payments();
+        if !payments.is_empty() {
+            self.send()
+                .direct_multi(&claim_address_mapper.get(), &payments);
+        }
+
+        payments
+    }
+
+    #[payable("*")]
+    #[endpoint]
+    fn deposit(&self) {
+        self.require_not_paused();
+
+        let payments = self.get_deposit_payments();
         let caller = self.blockchain().get_caller();
-        let caller_id = self.user_id().get_id_or_insert(&caller);
-        self.add_user_payment(payment, self.user_deposited_fees(caller_id));
+        let _ = self.deposit_user_payments(&caller, payments);
     }
 
     #[endpoint(withdrawFunds)]
@@ -97,8 +161,11 @@ pub trait FeesModule:
             }
         }
 
+        self.require_withdrawal_reserve_kept(caller_id, &output_payments, &all_user_tokens);
+
         if !output_payments.is_empty() {
             self.send().direct_multi(&caller, &output_payments);
+            self.emit_withdraw_event(caller_id, output_payments.clone());
         }
 
         user_fees_mapper.set(&UniquePayments::new_from_unique_payments(all_user_tokens));
@@ -106,6 +173,365 @@ pub trait FeesModule:
         output_payments
     }
 
+    /// Sets the order in which the caller's tokens are used for services that accept any token.
+    /// Deposited tokens missing from the list are used afterwards, in their deposit order.
+    #[endpoint(setTokenPreferenceOrder)]
+    fn set_token_preference_order(&self, tokens: MultiValueEncoded<TokenIdentifier>) {
+        let caller = self.blockchain().get_caller();
+        let caller_id = self.user_id().get_id_non_zero(&caller);
+        require!(
+            tokens.len() <= MAX_USER_DEPOSITS,
+            "Too many preferred tokens"
+        );
+
+        let mut preference_order = ManagedVec::<Self::Api, TokenIdentifier>::new();
+        for token_id in tokens {
+            require!(
+                self.accepted_fees_tokens().contains(&token_id),
+                "Invalid token"
+            );
+            require!(!preference_order.contains(&token_id), "Duplicate token");
+
+            preference_order.push(token_id);
+        }
+
+        self.user_token_preference_order(caller_id)
+            .set(preference_order);
+    }
+
+    /// Tokens excluded by the caller are never used for services that accept any token. Providing no tokens removes all exclusions.
+    #[endpoint(setExcludedTokens)]
+    fn set_excluded_tokens(&self, tokens: MultiValueEncoded<TokenIdentifier>) {
+        let caller = self.blockchain().get_caller();
+        let caller_id = self.user_id().get_id_non_zero(&caller);
+        let mut excluded_tokens_mapper = self.user_excluded_tokens(caller_id);
+        excluded_tokens_mapper.clear();
+        for token_id in tokens {
+            require!(
+                self.accepted_fees_tokens().contains(&token_id),
+                "Invalid token"
+            );
+
+            let _ = excluded_tokens_mapper.insert(token_id);
+        }
+    }
+
+    /// Returns the transferred tokens, or the WEGLD obtained by wrapping the transferred EGLD
+    fn get_deposit_payments(&self) -> ManagedVec<EsdtTokenPayment> {
+        match self.call_value().any_payment() {
+            EgldOrMultiEsdtPayment::Egld(egld_amount) => {
+                require!(egld_amount > 0, "No payment");
+
+                ManagedVec::from_single_item(self.wrap_egld(egld_amount))
+            }
+            EgldOrMultiEsdtPayment::MultiEsdt(payments) => payments,
+        }
+    }
+
+    fn wrap_egld(&self, egld_amount: BigUint) -> EsdtTokenPayment {
+        let wrapper_address_mapper = self.egld_wrapper_address();
+        require!(
+            !wrapper_address_mapper.is_empty(),
+            "EGLD wrapper address not set"
+        );
+
+        let wrapped_payment: EsdtTokenPayment = self
+            .egld_wrapper_proxy(wrapper_address_mapper.get())
+            .wrap_egld()
+            .with_egld_transfer(egld_amount)
+            .execute_on_dest_context();
+        require!(
+            wrapped_payment.token_identifier == self.wegld_token_id().get(),
+            "Invalid wrapped token"
+        );
+
+        wrapped_payment
+    }
+
+    /// Withdrawals that would go below the funds reserved for the next cycles can be requested instead,
+    /// and claimed after the withdrawal delay, regardless of the reserve. A new request replaces the previous one.
+    #[endpoint(requestWithdrawal)]
+    fn request_withdrawal(
+        &self,
+        tokens_to_withdraw: MultiValueEncoded<MultiValue2<TokenIdentifier, BigUint>>,
+    ) {
+        let delay_mapper = self.withdrawal_delay_epochs();
+        require!(!delay_mapper.is_empty(), "Withdrawal delay not set");
+
+        let caller = self.blockchain().get_caller();
+        let caller_id = self.user_id().get_id_non_zero(&caller);
+        let mut payments = ManagedVec::new();
+        for pair in tokens_to_withdraw {
+            let (token_id, amount) = pair.into_tuple();
+            require!(
+                self.accepted_fees_tokens().contains(&token_id),
+                "Invalid token"
+            );
+            require!(amount > 0, "Invalid amount");
+
+            payments.push(EsdtTokenPayment::new(token_id, 0, amount));
+        }
+
+        require!(!payments.is_empty(), "No tokens to withdraw");
+
+        let unlock_epoch = self.blockchain().get_block_epoch() + delay_mapper.get();
+        self.pending_withdrawal(caller_id).set(PendingWithdrawal {
+            payments,
+            unlock_epoch,
+        });
+    }
+
+    /// Withdraws the requested tokens, capped to the caller's current balance, as charges may have happened in the meantime
+    #[endpoint(claimWithdrawal)]
+    fn claim_withdrawal(&self) -> ManagedVec<EsdtTokenPayment> {
+        let caller = self.blockchain().get_caller();
+        let caller_id = self.user_id().get_id_non_zero(&caller);
+        let pending_withdrawal_mapper = self.pending_withdrawal(caller_id);
+        require!(
+            !pending_withdrawal_mapper.is_empty(),
+            "No pending withdrawal"
+        );
+
+        let pending_withdrawal = pending_withdrawal_mapper.get();
+        require!(
+            self.blockchain().get_block_epoch() >= pending_withdrawal.unlock_epoch,
+            "Withdrawal still locked"
+        );
+
+        pending_withdrawal_mapper.clear();
+
+        let user_fees_mapper = self.user_deposited_fees(caller_id);
+        let mut user_fees = if user_fees_mapper.is_empty() {
+            UniquePayments::new()
+        } else {
+            user_fees_mapper.get()
+        };
+        let all_user_tokens = user_fees.clone().into_payments();
+        let mut output_payments = ManagedVec::new();
+        for requested_payment in pending_withdrawal.payments.iter() {
+            for user_payment in all_user_tokens.iter() {
+                if user_payment.token_identifier == requested_payment.token_identifier {
+                    let amount = core::cmp::min(requested_payment.amount, user_payment.amount);
+                    if amount == 0 {
+                        break;
+                    }
+
+                    output_payments.push(EsdtTokenPayment::new(
+                        requested_payment.token_identifier,
+                        0,
+                        amount,
+                    ));
+                    break;
+                }
+            }
+        }
+
+        for payment in output_payments.iter() {
+            let _ = user_fees.deduct_payment(&payment);
+        }
+
+        if !output_payments.is_empty() {
+            self.send().direct_multi(&caller, &output_payments);
+            self.emit_withdraw_event(caller_id, output_payments.clone());
+        }
+
+        user_fees_mapper.set(user_fees);
+
+        output_payments
+    }
+
+    fn require_withdrawal_reserve_kept(
+        &self,
+        user_id: AddressId,
+        withdrawn_tokens: &ManagedVec<EsdtTokenPayment>,
+        remaining_tokens: &ManagedVec<EsdtTokenPayment>,
+    ) {
+        for reserved_payment in self.get_withdrawal_reserve(user_id).iter() {
+            // a reserve the user is already short on must not block withdrawing other tokens
+            let is_withdrawn = withdrawn_tokens
+                .iter()
+                .any(|payment| payment.token_identifier == reserved_payment.token_identifier);
+            if !is_withdrawn {
+                continue;
+            }
+
+            let mut remaining_amount = BigUint::zero();
+            for remaining_token in remaining_tokens.iter() {
+                if remaining_token.token_identifier == reserved_payment.token_identifier {
+                    remaining_amount = remaining_token.amount;
+                    break;
+                }
+            }
+
+            require!(
+                remaining_amount >= reserved_payment.amount,
+                "Funds reserved for the next cycle"
+            );
+        }
+    }
+
+    /// The next cycle of each subscription to a service option with the withdrawal reserve enabled
+    fn get_withdrawal_reserve(&self, user_id: AddressId) -> ManagedVec<EsdtTokenPayment> {
+        let mut reserve = UniquePayments::new();
+        for subscription in self.user_subscriptions(user_id).iter() {
+            let service_id = subscription.service_id;
+            let service_index = subscription.service_index;
+            // escrowed cycles are already out of the user's deposits
+            if !self
+                .withdrawal_reserve_enabled(service_id, service_index)
+                .get()
+                || !self
+                    .cycle_escrow(user_id, service_id, service_index)
+                    .is_empty()
+            {
+                continue;
+            }
+
+            let service_options = self.service_info(service_id).get();
+            if service_index >= service_options.len() {
+                continue;
+            }
+
+            let service_info = service_options.get(service_index);
+            let token_id = match service_info.opt_payment_token {
+                Some(token_id) => token_id,
+                None => continue,
+            };
+
+            let amount = if service_info.payment_in_stable {
+                // a cycle that can't be priced is not reserved, so withdrawals never depend on the price sources
+                let query_result = self.get_worth_of_price(&token_id, service_info.amount);
+                if query_result.is_err() {
+                    continue;
+                }
+
+                unsafe { query_result.unwrap_unchecked() }
+            } else {
+                service_info.amount
+            };
+
+            reserve.add_payment(EsdtTokenPayment::new(token_id, 0, amount));
+        }
+
+        reserve.into_payments()
+    }
+
+    fn deposit_user_payments(
+        &self,
+        user: &ManagedAddress,
+        payments: ManagedVec<EsdtTokenPayment>,
+    ) -> AddressId {
+        require!(!payments.is_empty(), "No payment");
+
+        let min_stable_token_deposit_value = self.min_stable_token_deposit_value().get();
+        for payment in payments.iter() {
+            self.require_valid_deposit(&payment, &min_stable_token_deposit_value);
+        }
+
+        let user_id = self.user_id().get_id_or_insert(user);
+        let user_fees_mapper = self.user_deposited_fees(user_id);
+        let mut user_fees = if user_fees_mapper.is_empty() {
+            UniquePayments::new()
+        } else {
+            user_fees_mapper.get()
+        };
+        for payment in payments.iter() {
+            user_fees.add_payment(payment.clone());
+            self.emit_deposit_event(user_id, payment);
+        }
+
+        require!(
+            user_fees.clone().into_payments().len() <= MAX_USER_DEPOSITS,
+            "Maximum number of deposits per user reached"
+        );
+
+        user_fees_mapper.set(user_fees);
+
+        user_id
+    }
+
+    fn require_valid_deposit(
+        &self,
+        payment: &EsdtTokenPayment,
+        min_stable_token_deposit_value: &BigUint,
+    ) {
+        require!(payment.amount > 0, "No payment");
+        require!(payment.token_nonce == 0, "Can deposit only fungible tokens");
+        require!(
+            self.accepted_fees_tokens()
+                .contains(&payment.token_identifier),
+            "Invalid payment token"
+        );
+
+        let min_payment_value_result = self.get_worth_of_price(
+            &payment.token_identifier,
+            min_stable_token_deposit_value.clone(),
+        );
+        require!(
+            min_payment_value_result.is_ok(),
+            "Could not get payment value"
+        );
+        let min_payment_value = unsafe { min_payment_value_result.unwrap_unchecked() };
+
+        require!(
+            payment.amount >= min_payment_value,
+            "Payment value is lesser than the minimum accepted"
+        );
+    }
+
+    /// Orders the user's deposited tokens by their preference, followed by the rest in deposit order
+    fn get_user_payment_tokens_in_order(
+        &self,
+        user_id: AddressId,
+        user_tokens: &ManagedVec<EsdtTokenPayment>,
+    ) -> ManagedVec<TokenIdentifier> {
+        let mut ordered_tokens = ManagedVec::<Self::Api, TokenIdentifier>::new();
+        for token_id in self.user_token_preference_order(user_id).get().iter() {
+            if user_tokens
+                .iter()
+                .any(|user_token| user_token.token_identifier == *token_id)
+            {
+                ordered_tokens.push((*token_id).clone());
+            }
+        }
+
+        for user_token in user_tokens.iter() {
+            if !ordered_tokens.contains(&user_token.token_identifier) {
+                ordered_tokens.push(user_token.token_identifier);
+            }
+        }
+
+        ordered_tokens
+    }
+
+    /// Keeps the protocol fee out of the charged payment, which is left with the amount for the service.
+    /// Returns the fee payment, if any fee was taken.
+    fn take_protocol_fee(&self, payment: &mut EsdtTokenPayment) -> Option<EsdtTokenPayment> {
+        let protocol_fee_percentage = self.protocol_fee_percentage().get();
+        if protocol_fee_percentage == 0 {
+            return None;
+        }
+
+        let fee_amount = &payment.amount * protocol_fee_percentage / MAX_PERCENTAGE;
+        if fee_amount == 0 {
+            return None;
+        }
+
+        payment.amount -= &fee_amount;
+
+        let fee_payment = EsdtTokenPayment::new(payment.token_identifier.clone(), 0, fee_amount);
+        let protocol_fees_mapper = self.protocol_fees();
+        let mut protocol_fees = if protocol_fees_mapper.is_empty() {
+            UniquePayments::new()
+        } else {
+            protocol_fees_mapper.get()
+        };
+        protocol_fees.add_payment(fee_payment.clone());
+        protocol_fees_mapper.set(protocol_fees);
+
+        Some(fee_payment)
+    }
+
     fn add_user_payment(
         &self,
         payment: EsdtTokenPayment,
@@ -128,4 +554,10 @@ pub trait FeesModule:
             });
         }
     }
+
+    #[proxy]
+    fn egld_wrapper_proxy(
+        &self,
+        sc_address: ManagedAddress,
+    ) -> egld_wrapper_proxy::Proxy<Self::Api>;
 }
@@ -2,11 +2,18 @@
 
 multiversx_sc::imports!();
 
+pub mod auto_swap;
 pub mod common_storage;
+pub mod escrow;
+pub mod events;
 pub mod fees;
 pub mod pair_actions;
+pub mod revenue;
 pub mod service;
+pub mod service_profile;
+pub mod spending_limits;
 pub mod subtract_payments;
+pub mod views;
 
 #[multiversx_sc::contract]
 pub trait SubscriptionFee:
@@ -15,7 +22,16 @@ pub trait SubscriptionFee:
     + service::ServiceModule
     + subtract_payments::SubtractPaymentsModule
     + pair_actions::PairActionsModule
+    + events::EventsModule
+    + views::ViewsModule
     + multiversx_sc_modules::ongoing_operation::OngoingOperationModule
+    + multiversx_sc_modules::pause::PauseModule
+    + energy_query::EnergyQueryModule
+    + spending_limits::SpendingLimitsModule
+    + auto_swap::AutoSwapModule
+    + escrow::EscrowModule
+    + revenue::RevenueModule
+    + service_profile::ServiceProfileModule
 {
     /// Price query address: The address to gather the token to USDC price
     /// Accepted tokens: The tokens users can deposit for fees
@@ -1,6 +1,25 @@
 multiversx_sc::imports!();
 multiversx_sc::derive_imports!();
 
+pub const MAX_PRICE_ROUTE_HOPS: usize = 4;
+pub const MAX_PERCENTAGE: u64 = 10_000;
+pub const PRICE_PRECISION: u64 = 1_000_000_000_000_000_000;
+
+/// FixedRate is the amount of tokens received for one unit of stable token, multiplied by PRICE_PRECISION
+#[derive(TypeAbi, TopEncode, TopDecode, NestedEncode, NestedDecode, Clone, PartialEq, Debug)]
+pub enum PriceSource<M: ManagedTypeApi> {
+    DexSafePrice,
+    PriceAggregator,
+    FixedRate(BigUint<M>),
+}
+
+/// Names of the price aggregator feed of a token, like EGLD/USD, which may differ from the token tickers
+#[derive(TypeAbi, TopEncode, TopDecode, NestedEncode, NestedDecode, Clone, PartialEq, Debug)]
+pub struct PriceFeedNames<M: ManagedTypeApi> {
+    pub from: ManagedBuffer<M>,
+    pub to: ManagedBuffer<M>,
+}
+
 pub mod pair_proxy {
     #[multiversx_sc::proxy]
     pub trait PairProxy {
@@ -10,6 +29,42 @@ pub mod pair_proxy {
             pair_address: ManagedAddress,
             input_payment: EsdtTokenPayment,
         ) -> EsdtTokenPayment;
+
+        #[view(getAmountOut)]
+        fn get_amount_out(&self, token_in: TokenIdentifier, amount_in: BigUint) -> BigUint;
+
+        #[payable("*")]
+        #[endpoint(swapTokensFixedInput)]
+        fn swap_tokens_fixed_input(
+            &self,
+            token_out: TokenIdentifier,
+            amount_out_min: BigUint,
+        ) -> EsdtTokenPayment;
+    }
+}
+
+pub mod price_aggregator_proxy {
+    multiversx_sc::imports!();
+    multiversx_sc::derive_imports!();
+
+    #[derive(TypeAbi, TopEncode, TopDecode, NestedEncode, NestedDecode)]
+    pub struct PriceFeed<M: ManagedTypeApi> {
+        pub round_id: u32,
+        pub from: ManagedBuffer<M>,
+        pub to: ManagedBuffer<M>,
+        pub timestamp: u64,
+        pub price: BigUint<M>,
+        pub decimals: u8,
+    }
+
+    #[multiversx_sc::proxy]
+    pub trait PriceAggregatorProxy {
+        #[view(latestPriceFeedOptional)]
+        fn latest_price_feed_optional(
+            &self,
+            from: ManagedBuffer,
+            to: ManagedBuffer,
+        ) -> OptionalValue<PriceFeed<Self::Api>>;
     }
 }
 
@@ -37,6 +92,161 @@ pub trait PairActionsModule: crate::common_storage::CommonStorageModule {
         self.pair_address_for_token(&token_id).clear();
     }
 
+    /// Route is the list of pair addresses used to convert a stable token amount into the given token.
+    /// The first pair must contain the stable token, and each pair must contain the output token of the previous one.
+    #[only_owner]
+    #[endpoint(setTokenPriceRoute)]
+    fn set_token_price_route(
+        &self,
+        token_id: TokenIdentifier,
+        route: MultiValueEncoded<ManagedAddress>,
+    ) {
+        require!(token_id.is_valid_esdt_identifier(), "Invalid token ID");
+        require!(
+            !route.is_empty() && route.len() <= MAX_PRICE_ROUTE_HOPS,
+            "Invalid route length"
+        );
+
+        let mut current_token_id = self.stable_token_id().get();
+        let mut pair_addresses = ManagedVec::new();
+        for pair_address in route {
+            require!(
+                self.blockchain().is_smart_contract(&pair_address),
+                "Invalid pair address"
+            );
+
+            let first_token_id = self.first_token_id().get_from_address(&pair_address);
+            let second_token_id = self.second_token_id().get_from_address(&pair_address);
+            current_token_id = if first_token_id == current_token_id {
+                second_token_id
+            } else if second_token_id == current_token_id {
+                first_token_id
+            } else {
+                sc_panic!("Invalid route");
+            };
+
+            pair_addresses.push(pair_address);
+        }
+
+        require!(current_token_id == token_id, "Route does not end in token");
+
+        self.token_price_route(&token_id).set(pair_addresses);
+    }
+
+    #[only_owner]
+    #[endpoint(removeTokenPriceRoute)]
+    fn remove_token_price_route(&self, token_id: TokenIdentifier) {
+        self.token_price_route(&token_id).clear();
+    }
+
+    #[only_owner]
+    #[endpoint(setPriceAggregatorAddress)]
+    fn set_price_aggregator_address(&self, price_aggregator_address: ManagedAddress) {
+        require!(
+            self.blockchain()
+                .is_smart_contract(&price_aggregator_address),
+            "Invalid price aggregator address"
+        );
+
+        self.price_aggregator_address()
+            .set(price_aggregator_address);
+    }
+
+    /// Number of decimals of the token, needed to convert the price aggregator prices.
+    /// Must also be set for the stable token when using the price aggregator.
+    #[only_owner]
+    #[endpoint(setTokenDecimals)]
+    fn set_token_decimals(&self, token_id: TokenIdentifier, decimals: u32) {
+        require!(token_id.is_valid_esdt_identifier(), "Invalid token ID");
+
+        self.token_decimals(&token_id).set(decimals);
+    }
+
+    /// The optional feed names are used to query the price aggregator for the token, whatever its price source,
+    /// as it is also queried for the deviation check. By default, the feed is named after the token and stable token tickers.
+    #[only_owner]
+    #[endpoint(setTokenPriceSource)]
+    fn set_token_price_source(
+        &self,
+        token_id: TokenIdentifier,
+        price_source: PriceSource<Self::Api>,
+        opt_feed_names: OptionalValue<MultiValue2<ManagedBuffer, ManagedBuffer>>,
+    ) {
+        require!(token_id.is_valid_esdt_identifier(), "Invalid token ID");
+
+        match opt_feed_names {
+            OptionalValue::Some(feed_names) => {
+                let (from, to) = feed_names.into_tuple();
+                require!(!from.is_empty() && !to.is_empty(), "Invalid feed names");
+
+                self.token_price_feed_names(&token_id)
+                    .set(PriceFeedNames { from, to });
+            }
+            OptionalValue::None => self.token_price_feed_names(&token_id).clear(),
+        }
+
+        match &price_source {
+            PriceSource::DexSafePrice => {
+                // DEX prices are checked against the price aggregator while a max deviation is set
+                if !self.max_price_deviation().is_empty() {
+                    self.require_price_aggregator_configured(&token_id);
+                }
+
+                self.token_price_source(&token_id).clear();
+                return;
+            }
+            PriceSource::PriceAggregator => {
+                self.require_price_aggregator_configured(&token_id);
+            }
+            PriceSource::FixedRate(rate) => {
+                require!(rate > &0, "Invalid rate");
+            }
+        }
+
+        self.token_price_source(&token_id).set(price_source);
+    }
+
+    /// Maximum age in seconds of a price aggregator price. Setting it to 0 disables the check.
+    #[only_owner]
+    #[endpoint(setMaxPriceStaleness)]
+    fn set_max_price_staleness(&self, max_price_staleness_seconds: u64) {
+        if max_price_staleness_seconds == 0 {
+            self.max_price_staleness().clear();
+        } else {
+            self.max_price_staleness().set(max_price_staleness_seconds);
+        }
+    }
+
+    /// Maximum deviation between the DEX and the price aggregator prices, where 10,000 = 100%.
+    /// Setting it to 0 disables the check. While it is set, tokens priced by the DEX or the price aggregator
+    /// cannot be charged if either price is unavailable, so the price aggregator must be configured for
+    /// all the accepted tokens that do not use a fixed rate.
+    #[only_owner]
+    #[endpoint(setMaxPriceDeviation)]
+    fn set_max_price_deviation(&self, max_price_deviation: u64) {
+        require!(
+            max_price_deviation <= MAX_PERCENTAGE,
+            "Invalid max price deviation"
+        );
+
+        if max_price_deviation == 0 {
+            self.max_price_deviation().clear();
+            return;
+        }
+
+        let stable_token_id = self.stable_token_id().get();
+        for token_id in self.accepted_fees_tokens().iter() {
+            let price_source_mapper = self.token_price_source(&token_id);
+            let has_fixed_rate = !price_source_mapper.is_empty()
+                && matches!(price_source_mapper.get(), PriceSource::FixedRate(_));
+            if token_id != stable_token_id && !has_fixed_rate {
+                self.require_price_aggregator_configured(&token_id);
+            }
+        }
+
+        self.max_price_deviation().set(max_price_deviation);
+    }
+
     fn get_worth_of_price(
         &self,
         desired_token_id: &TokenIdentifier,
@@ -47,6 +257,154 @@ pub trait PairActionsModule: crate::common_storage::CommonStorageModule {
             return Result::Ok(stable_worth_amount);
         }
 
+        let price_source_mapper = self.token_price_source(desired_token_id);
+        let price_source = if price_source_mapper.is_empty() {
+            PriceSource::DexSafePrice
+        } else {
+            price_source_mapper.get()
+        };
+
+        match price_source {
+            PriceSource::DexSafePrice => {
+                let dex_price =
+                    self.get_dex_worth_of_price(desired_token_id, &stable_worth_amount)?;
+                if self.max_price_deviation().is_empty() {
+                    return Result::Ok(dex_price);
+                }
+
+                let aggregator_price =
+                    self.get_aggregator_worth_of_price(desired_token_id, &stable_worth_amount)?;
+                self.check_price_deviation(dex_price, &aggregator_price)
+            }
+            PriceSource::PriceAggregator => {
+                let aggregator_price =
+                    self.get_aggregator_worth_of_price(desired_token_id, &stable_worth_amount)?;
+                if self.max_price_deviation().is_empty() {
+                    return Result::Ok(aggregator_price);
+                }
+
+                let dex_price =
+                    self.get_dex_worth_of_price(desired_token_id, &stable_worth_amount)?;
+                self.check_price_deviation(aggregator_price, &dex_price)
+            }
+            PriceSource::FixedRate(rate) => {
+                Result::Ok(stable_worth_amount * rate / PRICE_PRECISION)
+            }
+        }
+    }
+
+    /// Fails if the price deviates from the reference price more than the allowed maximum
+    fn check_price_deviation(
+        &self,
+        price: BigUint,
+        reference_price: &BigUint,
+    ) -> Result<BigUint, ()> {
+        let difference = if &price > reference_price {
+            &price - reference_price
+        } else {
+            reference_price - &price
+        };
+        let max_price_deviation = self.max_price_deviation().get();
+        if difference * MAX_PERCENTAGE > reference_price * max_price_deviation {
+            return Result::Err(());
+        }
+
+        Result::Ok(price)
+    }
+
+    fn require_price_aggregator_configured(&self, token_id: &TokenIdentifier) {
+        require!(
+            !self.price_aggregator_address().is_empty(),
+            "Price aggregator address not set"
+        );
+        require!(
+            !self.token_decimals(token_id).is_empty()
+                && !self
+                    .token_decimals(&self.stable_token_id().get())
+                    .is_empty(),
+            "Token decimals not set"
+        );
+    }
+
+    fn get_aggregator_worth_of_price(
+        &self,
+        desired_token_id: &TokenIdentifier,
+        stable_worth_amount: &BigUint,
+    ) -> Result<BigUint, ()> {
+        let stable_token_id = self.stable_token_id().get();
+        // tokens accepted after the max deviation was set may not be configured yet
+        if self.price_aggregator_address().is_empty()
+            || self.token_decimals(desired_token_id).is_empty()
+            || self.token_decimals(&stable_token_id).is_empty()
+        {
+            return Result::Err(());
+        }
+
+        let price_aggregator_address = self.price_aggregator_address().get();
+        let feed_names = self.get_price_feed_names(desired_token_id, &stable_token_id);
+        let opt_price_feed: OptionalValue<price_aggregator_proxy::PriceFeed<Self::Api>> = self
+            .price_aggregator_proxy(price_aggregator_address)
+            .latest_price_feed_optional(feed_names.from, feed_names.to)
+            .execute_on_dest_context();
+
+        let price_feed = match opt_price_feed {
+            OptionalValue::Some(price_feed) => price_feed,
+            OptionalValue::None => return Result::Err(()),
+        };
+        if price_feed.price == 0 {
+            return Result::Err(());
+        }
+
+        let max_price_staleness_mapper = self.max_price_staleness();
+        if !max_price_staleness_mapper.is_empty() {
+            let current_timestamp = self.blockchain().get_block_timestamp();
+            if price_feed.timestamp + max_price_staleness_mapper.get() < current_timestamp {
+                return Result::Err(());
+            }
+        }
+
+        // the price feed is the price of one whole token, expressed in whole stable tokens
+        let token_decimals = self.token_decimals(desired_token_id).get();
+        let stable_token_decimals = self.token_decimals(&stable_token_id).get();
+        let ten = BigUint::from(10u32);
+        let token_amount =
+            stable_worth_amount * &ten.pow(token_decimals) * &ten.pow(price_feed.decimals as u32)
+                / (price_feed.price * ten.pow(stable_token_decimals));
+
+        Result::Ok(token_amount)
+    }
+
+    fn get_price_feed_names(
+        &self,
+        token_id: &TokenIdentifier,
+        stable_token_id: &TokenIdentifier,
+    ) -> PriceFeedNames<Self::Api> {
+        let feed_names_mapper = self.token_price_feed_names(token_id);
+        if !feed_names_mapper.is_empty() {
+            return feed_names_mapper.get();
+        }
+
+        PriceFeedNames {
+            from: token_id.ticker(),
+            to: stable_token_id.ticker(),
+        }
+    }
+
+    fn get_dex_worth_of_price(
+        &self,
+        desired_token_id: &TokenIdentifier,
+        stable_worth_amount: &BigUint,
+    ) -> Result<BigUint, ()> {
+        let stable_token_id = self.stable_token_id().get();
+        let route_mapper = self.token_price_route(desired_token_id);
+        if !route_mapper.is_empty() {
+            return self.get_worth_of_price_by_route(
+                desired_token_id,
+                EsdtTokenPayment::new(stable_token_id, 0, stable_worth_amount.clone()),
+                route_mapper.get(),
+            );
+        }
+
         let wegld_token_id = self.wegld_token_id().get();
         let price_query_address = self.price_query_address().get();
         let stable_pair_data_mapper = self.pair_address_for_token(&wegld_token_id);
@@ -58,7 +416,7 @@ pub trait PairActionsModule: crate::common_storage::CommonStorageModule {
             .pair_proxy(price_query_address.clone())
             .get_safe_price_by_default_offset(
                 stable_pair_address,
-                EsdtTokenPayment::new(stable_token_id, 0, stable_worth_amount),
+                EsdtTokenPayment::new(stable_token_id, 0, stable_worth_amount.clone()),
             )
             .execute_on_dest_context();
 
@@ -84,6 +442,34 @@ pub trait PairActionsModule: crate::common_storage::CommonStorageModule {
         }
     }
 
+    fn get_worth_of_price_by_route(
+        &self,
+        desired_token_id: &TokenIdentifier,
+        stable_payment: EsdtTokenPayment,
+        route: ManagedVec<ManagedAddress>,
+    ) -> Result<BigUint, ()> {
+        let price_query_address = self.price_query_address().get();
+        let mut price = stable_payment;
+        for pair_address in &route {
+            price = self
+                .pair_proxy(price_query_address.clone())
+                .get_safe_price_by_default_offset(pair_address, price)
+                .execute_on_dest_context();
+        }
+
+        if &price.token_identifier == desired_token_id {
+            Result::Ok(price.amount)
+        } else {
+            Result::Err(())
+        }
+    }
+
     #[proxy]
     fn pair_proxy(&self, sc_address: ManagedAddress) -> pair_proxy::Proxy<Self::Api>;
+
+    #[proxy]
+    fn price_aggregator_proxy(
+        &self,
+        sc_address: ManagedAddress,
+    ) -> price_aggregator_proxy::Proxy<Self::Api>;
 }
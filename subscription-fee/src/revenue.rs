@@ -0,0 +1,130 @@
+multiversx_sc::imports!();
+
+use common_structs::UniquePayments;
+
+#[multiversx_sc::module]
+pub trait RevenueModule:
+    crate::common_storage::CommonStorageModule
+    + crate::events::EventsModule
+    + crate::service_profile::ServiceProfileModule
+{
+    /// In pull mode, charged tokens are kept in the contract instead of being sent to the service,
+    /// which claims them with claimServiceRevenue. Useful for services that cannot receive tokens directly.
+    #[endpoint(setRevenuePullMode)]
+    fn set_revenue_pull_mode(&self, enabled: bool) {
+        let service_address = self.blockchain().get_caller();
+        let service_id = self.get_service_id_non_zero(&service_address);
+        if enabled {
+            self.revenue_pull_mode(service_id).set(true);
+        } else {
+            self.revenue_pull_mode(service_id).clear();
+        }
+    }
+
+    /// Sends all the revenue kept for the caller service to the given address
+    #[endpoint(claimServiceRevenue)]
+    fn claim_service_revenue(&self, destination: ManagedAddress) -> ManagedVec<EsdtTokenPayment> {
+        let service_address = self.blockchain().get_caller();
+        let service_id = self.get_service_id_non_zero(&service_address);
+
+        self.send_kept_service_revenue(service_id, destination)
+    }
+
+    /// Sends the revenue an unregistered service did not claim to the given address.
+    /// Only the last owner of the service can claim it.
+    #[endpoint(claimUnregisteredServiceRevenue)]
+    fn claim_unregistered_service_revenue(
+        &self,
+        service_id: AddressId,
+        destination: ManagedAddress,
+    ) -> ManagedVec<EsdtTokenPayment> {
+        let revenue_owner_mapper = self.unregistered_service_revenue_owner(service_id);
+        require!(!revenue_owner_mapper.is_empty(), "No revenue to claim");
+
+        let caller = self.blockchain().get_caller();
+        require!(
+            revenue_owner_mapper.get() == caller,
+            "Only the service owner can call this"
+        );
+
+        revenue_owner_mapper.clear();
+
+        self.send_kept_service_revenue(service_id, destination)
+    }
+
+    /// The revenue is not sent on unregister, as pull mode services may not be able to receive it.
+    /// It stays claimable by the last owner instead.
+    fn keep_unclaimed_service_revenue(&self, service_id: AddressId) {
+        if !self.service_revenue(service_id).is_empty() {
+            let owner = self.get_service_profile(service_id).owner;
+            self.unregistered_service_revenue_owner(service_id)
+                .set(owner);
+        }
+
+        self.revenue_pull_mode(service_id).clear();
+    }
+
+    fn send_kept_service_revenue(
+        &self,
+        service_id: AddressId,
+        destination: ManagedAddress,
+    ) -> ManagedVec<EsdtTokenPayment> {
+        let revenue_mapper = self.service_revenue(service_id);
+        if revenue_mapper.is_empty() {
+            return ManagedVec::new();
+        }
+
+        let payments = revenue_mapper.take().into_payments();
+        if !payments.is_empty() {
+            self.send().direct_multi(&destination, &payments);
+            self.emit_claim_service_revenue_event(service_id, destination, payments.clone());
+        }
+
+        payments
+    }
+
+    /// Sends the charged payments to the payout address of the service, or keeps them as claimable revenue if it uses pull mode.
+    /// Charges rounded down to zero are skipped, as zero amounts cannot be transferred.
+    fn send_service_revenue(&self, service_id: AddressId, payments: &ManagedVec<EsdtTokenPayment>) {
+        let mut non_zero_payments = ManagedVec::<Self::Api, EsdtTokenPayment>::new();
+        for payment in payments.iter() {
+            if payment.amount > 0 {
+                non_zero_payments.push(payment);
+            }
+        }
+
+        if non_zero_payments.is_empty() {
+            return;
+        }
+
+        if !self.revenue_pull_mode(service_id).get() {
+            let payout_address = self.get_service_profile(service_id).payout_address;
+            self.send()
+                .direct_multi(&payout_address, &non_zero_payments);
+            return;
+        }
+
+        let revenue_mapper = self.service_revenue(service_id);
+        let mut revenue = if revenue_mapper.is_empty() {
+            UniquePayments::new()
+        } else {
+            revenue_mapper.get()
+        };
+        for payment in non_zero_payments.iter() {
+            revenue.add_payment(payment);
+        }
+
+        revenue_mapper.set(revenue);
+    }
+
+    fn add_lifetime_revenue(&self, service_id: AddressId, payment: EsdtTokenPayment) {
+        let lifetime_revenue_mapper = self.service_lifetime_revenue(service_id);
+        let mut lifetime_revenue = if lifetime_revenue_mapper.is_empty() {
+            UniquePayments::new()
+        } else {
+            lifetime_revenue_mapper.get()
+        };
+        lifetime_revenue.add_payment(payment);
+        lifetime_revenue_mapper.set(lifetime_revenue);
+    }
+}
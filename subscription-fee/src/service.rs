@@ -1,12 +1,20 @@
 multiversx_sc::imports!();
 multiversx_sc::derive_imports!();
 
+use common_structs::UniquePayments;
+use multiversx_sc_modules::ongoing_operation::{
+    CONTINUE_OP, DEFAULT_MIN_GAS_TO_SAVE_PROGRESS, STOP_OP,
+};
+
 use crate::common_storage;
+use crate::pair_actions::MAX_PERCENTAGE;
+use crate::service_profile::MAX_SERVICE_OPTION_LABEL_LENGTH;
 use crate::subtract_payments::Epoch;
-use crate::{fees, pair_actions};
+use crate::{events, fees, pair_actions};
 
 pub const MAX_USER_DEPOSITS: usize = 20;
 pub const MAX_SERVICES_LENGTH: usize = 20;
+pub const MAX_ENERGY_DISCOUNT_TIERS: usize = 10;
 
 #[derive(TypeAbi, TopEncode, TopDecode, NestedEncode, NestedDecode, ManagedVecItem)]
 pub struct ServiceInfo<M: ManagedTypeApi> {
@@ -16,9 +24,45 @@ pub struct ServiceInfo<M: ManagedTypeApi> {
     pub subscription_epochs: Epoch,
 }
 
+#[derive(
+    TypeAbi, TopEncode, TopDecode, NestedEncode, NestedDecode, ManagedVecItem, PartialEq, Clone,
+)]
+pub struct UserSubscription {
+    pub service_id: AddressId,
+    pub service_index: usize,
+}
+
+/// Subscribers of each service option are walked from the last one, so unsubscribes during the backfill cannot skip anyone
+#[derive(TypeAbi, TopEncode, TopDecode)]
+pub struct UserSubscriptionsBackfillProgress {
+    pub service_id: AddressId,
+    pub service_index: usize,
+    pub remaining_users: usize,
+}
+
+#[derive(TypeAbi, TopEncode, TopDecode, NestedEncode, NestedDecode, ManagedVecItem)]
+pub struct EnergyDiscountTier<M: ManagedTypeApi> {
+    pub min_energy: BigUint<M>,
+    pub discount_percentage: u64,
+}
+
+#[derive(TypeAbi, TopEncode, TopDecode)]
+pub struct LastCharge<M: ManagedTypeApi> {
+    pub payment: EsdtTokenPayment<M>,
+    pub epoch: Epoch,
+}
+
 #[multiversx_sc::module]
 pub trait ServiceModule:
-    fees::FeesModule + pair_actions::PairActionsModule + common_storage::CommonStorageModule
+    fees::FeesModule
+    + pair_actions::PairActionsModule
+    + common_storage::CommonStorageModule
+    + events::EventsModule
+    + multiversx_sc_modules::pause::PauseModule
+    + crate::escrow::EscrowModule
+    + crate::revenue::RevenueModule
+    + crate::service_profile::ServiceProfileModule
+    + multiversx_sc_modules::ongoing_operation::OngoingOperationModule
 {
     /// Arguments are MultiValue4 of opt_payment_token, payment_amount, payment_in_stable and subscription_epochs
     #[endpoint(registerService)]
@@ -29,39 +73,66 @@ pub trait ServiceModule:
         require!(!args.is_empty(), "No arguments provided");
 
         let service_address = self.blockchain().get_caller();
-        let existing_service_id = self.service_id().get_id(&service_address);
-        require!(existing_service_id == NULL_ID, "Service already registered");
-
         let mut services = ManagedVec::<Self::Api, _>::new();
+        let mut labels = ManagedVec::new();
         for arg in args {
             let (opt_payment_token, amount, payment_in_stable, subscription_epochs) =
                 arg.into_tuple();
+            let service_info = self.build_service_info(
+                opt_payment_token,
+                amount,
+                payment_in_stable,
+                subscription_epochs,
+            );
 
-            require!(subscription_epochs > 0, "Subscription epochs must be > 0");
-            if let Some(token_id) = &opt_payment_token {
-                require!(
-                    self.accepted_fees_tokens().contains(token_id),
-                    "Invalid token ID"
-                );
-            }
+            services.push(service_info);
+            labels.push(ManagedBuffer::new());
+        }
+
+        self.add_pending_service_options(service_address, services, labels);
+    }
+
+    /// Same as registerService, with the service metadata and a label for each option, which can be empty.
+    /// Options arguments are MultiValue5 of opt_payment_token, payment_amount, payment_in_stable, subscription_epochs and label
+    #[endpoint(registerServiceWithMetadata)]
+    fn register_service_with_metadata(
+        &self,
+        name: ManagedBuffer,
+        description_hash: ManagedBuffer,
+        url: ManagedBuffer,
+        category: ManagedBuffer,
+        args: MultiValueEncoded<
+            MultiValue5<Option<TokenIdentifier>, BigUint, bool, Epoch, ManagedBuffer>,
+        >,
+    ) {
+        require!(!args.is_empty(), "No arguments provided");
+
+        let service_address = self.blockchain().get_caller();
+        let metadata = self.build_service_metadata(name, description_hash, url, category);
+        let mut services = ManagedVec::<Self::Api, _>::new();
+        let mut labels = ManagedVec::new();
+        for arg in args {
+            let (opt_payment_token, amount, payment_in_stable, subscription_epochs, label) =
+                arg.into_tuple();
+            require!(
+                label.len() <= MAX_SERVICE_OPTION_LABEL_LENGTH,
+                "Label too long"
+            );
 
-            services.push(ServiceInfo {
+            let service_info = self.build_service_info(
                 opt_payment_token,
                 amount,
                 payment_in_stable,
                 subscription_epochs,
-            });
+            );
+
+            services.push(service_info);
+            labels.push(label);
         }
 
-        self.pending_service_info(&service_address)
-            .update(|existing_services| {
-                existing_services.extend(services.iter());
-                require!(
-                    existing_services.len() <= MAX_SERVICES_LENGTH,
-                    "Maximum services length reached"
-                );
-            });
-        let _ = self.pending_services().insert(service_address);
+        self.add_pending_service_options(service_address.clone(), services, labels);
+        self.pending_service_metadata(&service_address)
+            .set(metadata);
     }
 
     #[endpoint(addExtraServices)]
@@ -72,28 +143,21 @@ pub trait ServiceModule:
         require!(!args.is_empty(), "No arguments provided");
 
         let service_address = self.blockchain().get_caller();
-        let existing_service_id = self.service_id().get_id(&service_address);
+        let existing_service_id = self.get_service_id(&service_address);
         require!(existing_service_id != NULL_ID, "Service not registered");
 
         let mut services = ManagedVec::<Self::Api, _>::new();
         for arg in args {
             let (opt_payment_token, amount, payment_in_stable, subscription_epochs) =
                 arg.into_tuple();
-
-            require!(subscription_epochs > 0, "Subscription epochs must be > 0");
-            if let Some(token_id) = &opt_payment_token {
-                require!(
-                    self.accepted_fees_tokens().contains(token_id),
-                    "Invalid token ID"
-                );
-            }
-
-            services.push(ServiceInfo {
+            let service_info = self.build_service_info(
                 opt_payment_token,
                 amount,
                 payment_in_stable,
                 subscription_epochs,
-            });
+            );
+
+            services.push(service_info);
         }
 
         let service_info_mapper = self.service_info(existing_service_id);
@@ -104,30 +168,93 @@ pub trait ServiceModule:
                 "Maximum services length reached"
             );
         });
+        self.emit_register_service_event(service_address, services);
+    }
+
+    /// Proposes new payment terms for an existing service option. The change must be approved by the owner,
+    /// and applies to each subscriber starting with their next charge. The option keeps its index.
+    #[endpoint(updateServiceOption)]
+    fn update_service_option(
+        &self,
+        service_index: usize,
+        opt_payment_token: Option<TokenIdentifier>,
+        amount: BigUint,
+        payment_in_stable: bool,
+        subscription_epochs: Epoch,
+    ) {
+        let service_address = self.blockchain().get_caller();
+        let service_id = self.get_service_id_non_zero(&service_address);
+        let service_options = self.service_info(service_id).get();
+        require!(
+            service_index < service_options.len(),
+            "Invalid service index"
+        );
+        require!(
+            !self
+                .deprecated_service_option(service_id, service_index)
+                .get(),
+            "Service option deprecated"
+        );
+
+        let service_info = self.build_service_info(
+            opt_payment_token,
+            amount,
+            payment_in_stable,
+            subscription_epochs,
+        );
+        self.pending_service_option_update(service_id, service_index)
+            .set(service_info);
+    }
+
+    #[only_owner]
+    #[endpoint(approveServiceOptionUpdate)]
+    fn approve_service_option_update(&self, service_address: ManagedAddress, service_index: usize) {
+        let service_id = self.get_service_id_non_zero(&service_address);
+        let pending_update_mapper = self.pending_service_option_update(service_id, service_index);
+        require!(!pending_update_mapper.is_empty(), "No pending update");
+
+        let service_info = pending_update_mapper.take();
+        self.service_info(service_id).update(|service_options| {
+            let _ = service_options.set(service_index, &service_info);
+        });
+
+        let version = self
+            .service_option_version(service_id, service_index)
+            .update(|version| {
+                *version += 1;
+                *version
+            });
+        self.emit_service_option_updated_event(service_id, service_index, version, service_info);
+    }
+
+    /// Deprecated options do not accept new subscribers. Existing subscribers are kept until they unsubscribe.
+    #[endpoint(deprecateServiceOption)]
+    fn deprecate_service_option(&self, service_index: usize) {
+        let service_address = self.blockchain().get_caller();
+        let service_id = self.get_service_id_non_zero(&service_address);
+        let service_options = self.service_info(service_id).get();
+        require!(
+            service_index < service_options.len(),
+            "Invalid service index"
+        );
+
+        self.deprecated_service_option(service_id, service_index)
+            .set(true);
+        self.pending_service_option_update(service_id, service_index)
+            .clear();
+        self.emit_service_option_deprecated_event(service_id, service_index);
     }
 
     #[endpoint(unregisterService)]
     fn unregister_service(&self) {
         let service_address = self.blockchain().get_caller();
-        let service_id = self.service_id().remove_by_address(&service_address);
-        if service_id != NULL_ID {
-            self.service_info(service_id).clear();
-        }
-
-        let _ = self.pending_services().swap_remove(&service_address);
-        self.pending_service_info(&service_address).clear();
+        self.remove_service(&service_address);
     }
 
     #[only_owner]
     #[endpoint(unregisterServiceByOwner)]
     fn unregister_service_by_owner(&self, service_address: ManagedAddress) {
-        let service_id = self.service_id().remove_by_address(&service_address);
-        if service_id != NULL_ID {
-            self.service_info(service_id).clear();
-        }
-
-        let _ = self.pending_services().swap_remove(&service_address);
-        self.pending_service_info(&service_address).clear();
+        self.remove_service(&service_address);
     }
 
     #[only_owner]
@@ -142,31 +269,299 @@ pub trait ServiceModule:
         let service_info = self.pending_service_info(&service_address).take();
         self.service_info(service_id).set(&service_info);
 
+        let pending_metadata_mapper = self.pending_service_metadata(&service_address);
+        let metadata = if pending_metadata_mapper.is_empty() {
+            self.empty_service_metadata()
+        } else {
+            pending_metadata_mapper.take()
+        };
+        self.service_profile(service_id)
+            .set(self.new_service_profile(service_address.clone(), metadata));
+
+        let labels = self.pending_service_option_labels(&service_address).take();
+        for (service_index, label) in labels.iter().enumerate() {
+            if !label.is_empty() {
+                self.service_option_label(service_id, service_index)
+                    .set(&*label);
+            }
+        }
+
         require!(
             self.service_info(service_id).get().len() <= MAX_SERVICES_LENGTH,
             "Maximum services lenght reached"
         );
 
         let _ = self.pending_services().swap_remove(&service_address);
+        self.emit_approve_service_event(service_id, service_address);
+    }
+
+    /// Number of epochs a user is kept as past due after a failed charge, before being unsubscribed automatically.
+    /// Setting it to 0 disables the automatic unsubscribe.
+    #[endpoint(setGracePeriod)]
+    fn set_grace_period(&self, grace_period_epochs: Epoch) {
+        let service_address = self.blockchain().get_caller();
+        let service_id = self.get_service_id_non_zero(&service_address);
+        if grace_period_epochs == 0 {
+            self.service_grace_period(service_id).clear();
+        } else {
+            self.service_grace_period(service_id)
+                .set(grace_period_epochs);
+        }
+    }
+
+    /// When enabled, anyone can charge the due subscribers of the caller service through processDueSubscriptions.
+    /// Services that keep their own records of each charge should leave it disabled and use subtractPayment instead.
+    #[endpoint(setAutomaticBilling)]
+    fn set_automatic_billing(&self, enabled: bool) {
+        let service_address = self.blockchain().get_caller();
+        let service_id = self.get_service_id_non_zero(&service_address);
+        if enabled {
+            self.automatic_billing_enabled(service_id).set(true);
+        } else {
+            self.automatic_billing_enabled(service_id).clear();
+        }
+    }
+
+    /// When enabled, users that unsubscribe from the given service option mid-cycle are owed the unused part of their last charge.
+    /// The refunds are paid back by the service through the payRefunds endpoint.
+    #[endpoint(setProratedRefunds)]
+    fn set_prorated_refunds(&self, service_index: usize, enabled: bool) {
+        let service_address = self.blockchain().get_caller();
+        let service_id = self.get_service_id_non_zero(&service_address);
+        let service_options = self.service_info(service_id).get();
+        require!(
+            service_index < service_options.len(),
+            "Invalid service index"
+        );
+
+        if enabled {
+            self.prorated_refunds_enabled(service_id, service_index)
+                .set(true);
+        } else {
+            self.prorated_refunds_enabled(service_id, service_index)
+                .clear();
+        }
+    }
+
+    /// When enabled, subscribers of the given service option cannot withdraw the funds needed for its next cycle.
+    /// Only available for options with a payment token, since the reserve is kept in that token.
+    #[endpoint(setWithdrawalReserve)]
+    fn set_withdrawal_reserve(&self, service_index: usize, enabled: bool) {
+        let service_address = self.blockchain().get_caller();
+        let service_id = self.get_service_id_non_zero(&service_address);
+        let service_options = self.service_info(service_id).get();
+        require!(
+            service_index < service_options.len(),
+            "Invalid service index"
+        );
+
+        if !enabled {
+            self.withdrawal_reserve_enabled(service_id, service_index)
+                .clear();
+            return;
+        }
+
+        require!(
+            service_options
+                .get(service_index)
+                .opt_payment_token
+                .is_some(),
+            "Reserve requires a payment token"
+        );
+
+        self.withdrawal_reserve_enabled(service_id, service_index)
+            .set(true);
+    }
+
+    /// New subscribers of the given service option get their first trial_epochs for free. Each user can only get the trial once.
+    /// Setting it to 0 disables the trial.
+    #[endpoint(setTrialPeriod)]
+    fn set_trial_period(&self, service_index: usize, trial_epochs: Epoch) {
+        let service_address = self.blockchain().get_caller();
+        let service_id = self.get_service_id_non_zero(&service_address);
+        let service_options = self.service_info(service_id).get();
+        require!(
+            service_index < service_options.len(),
+            "Invalid service index"
+        );
+
+        if trial_epochs == 0 {
+            self.service_trial_epochs(service_id, service_index).clear();
+        } else {
+            self.service_trial_epochs(service_id, service_index)
+                .set(trial_epochs);
+        }
+    }
+
+    /// Arguments are pairs of min_energy and discount_percentage, where 10,000 = 100%, in increasing order of energy.
+    /// Discounts must be lower than 100%, as services cannot be paid zero amounts.
+    /// Users are charged with the discount of the highest tier their energy reaches. Providing no tiers removes the discounts.
+    #[endpoint(setEnergyDiscountTiers)]
+    fn set_energy_discount_tiers(
+        &self,
+        service_index: usize,
+        tiers: MultiValueEncoded<MultiValue2<BigUint, u64>>,
+    ) {
+        let service_address = self.blockchain().get_caller();
+        let service_id = self.get_service_id_non_zero(&service_address);
+        let service_options = self.service_info(service_id).get();
+        require!(
+            service_index < service_options.len(),
+            "Invalid service index"
+        );
+        require!(
+            tiers.len() <= MAX_ENERGY_DISCOUNT_TIERS,
+            "Too many discount tiers"
+        );
+
+        let tiers_mapper = self.energy_discount_tiers(service_id, service_index);
+        if tiers.is_empty() {
+            tiers_mapper.clear();
+            return;
+        }
+
+        let mut discount_tiers = ManagedVec::<Self::Api, EnergyDiscountTier<Self::Api>>::new();
+        let mut previous_min_energy = BigUint::zero();
+        for tier in tiers {
+            let (min_energy, discount_percentage) = tier.into_tuple();
+            require!(
+                min_energy > previous_min_energy,
+                "Tiers must be in increasing order of energy"
+            );
+            require!(
+                discount_percentage > 0 && discount_percentage < MAX_PERCENTAGE,
+                "Invalid discount percentage"
+            );
+
+            previous_min_energy = min_energy.clone();
+            discount_tiers.push(EnergyDiscountTier {
+                min_energy,
+                discount_percentage,
+            });
+        }
+
+        tiers_mapper.set(discount_tiers);
+    }
+
+    /// Pays the pending refunds of the given users back into their deposits.
+    /// Users without pending refunds are skipped, so the call can be safely repeated. Any unused tokens are returned to the service.
+    #[payable("*")]
+    #[endpoint(payRefunds)]
+    fn pay_refunds(&self, user_ids: MultiValueEncoded<AddressId>) {
+        let service_address = self.blockchain().get_caller();
+        let service_id = self.get_service_id_non_zero(&service_address);
+        let payments = self.call_value().all_esdt_transfers().clone_value();
+        let mut available_payments = UniquePayments::new_from_payments(payments);
+
+        let mut users_with_refunds_mapper = self.users_with_pending_refunds(service_id);
+        for user_id in user_ids {
+            if !users_with_refunds_mapper.contains(&user_id) {
+                continue;
+            }
+
+            let user_refunds = self.pending_refunds(user_id, service_id).take();
+            for refund in user_refunds.into_payments().iter() {
+                let deduct_result = available_payments.deduct_payment(&refund);
+                require!(deduct_result.is_ok(), "Insufficient refund payment");
+
+                self.add_user_payment(refund.clone(), self.user_deposited_fees(user_id));
+                self.emit_refund_event(user_id, service_id, refund);
+            }
+
+            let _ = users_with_refunds_mapper.swap_remove(&user_id);
+        }
+
+        let mut leftover_payments = ManagedVec::<Self::Api, EsdtTokenPayment>::new();
+        for payment in available_payments.into_payments().iter() {
+            if payment.amount > 0 {
+                leftover_payments.push(payment);
+            }
+        }
+
+        if !leftover_payments.is_empty() {
+            self.send()
+                .direct_multi(&service_address, &leftover_payments);
+        }
+    }
+
+    /// Adds the subscriptions made before the upgrade that introduced the user subscriptions index.
+    /// Must be called until it completes, after which the index can be relied on. It can run while the contract is in use.
+    #[only_owner]
+    #[endpoint(backfillUserSubscriptions)]
+    fn backfill_user_subscriptions(&self) -> OperationCompletionStatus {
+        let progress_mapper = self.user_subscriptions_backfill_progress();
+        let mut progress = if progress_mapper.is_empty() {
+            UserSubscriptionsBackfillProgress {
+                service_id: 1,
+                service_index: 0,
+                remaining_users: self.get_users_to_backfill(1, 0),
+            }
+        } else {
+            progress_mapper.get()
+        };
+
+        let last_service_id = self.service_id().get_last_id();
+        let run_result = self.run_while_it_has_gas(DEFAULT_MIN_GAS_TO_SAVE_PROGRESS, || {
+            if progress.remaining_users > 0 {
+                let subscribed_users_mapper =
+                    self.subscribed_users(progress.service_id, progress.service_index);
+                if progress.remaining_users <= subscribed_users_mapper.len() {
+                    let user_id = subscribed_users_mapper.get_by_index(progress.remaining_users);
+                    let _ = self.user_subscriptions(user_id).insert(UserSubscription {
+                        service_id: progress.service_id,
+                        service_index: progress.service_index,
+                    });
+                }
+
+                progress.remaining_users -= 1;
+                return CONTINUE_OP;
+            }
+
+            progress.service_index += 1;
+            if progress.service_index >= self.service_info(progress.service_id).get().len() {
+                progress.service_id += 1;
+                progress.service_index = 0;
+            }
+
+            if progress.service_id > last_service_id {
+                return STOP_OP;
+            }
+
+            progress.remaining_users =
+                self.get_users_to_backfill(progress.service_id, progress.service_index);
+
+            CONTINUE_OP
+        });
+
+        if run_result == OperationCompletionStatus::InterruptedBeforeOutOfGas {
+            progress_mapper.set(progress);
+        } else {
+            progress_mapper.clear();
+        }
+
+        run_result
+    }
+
+    /// Unregistered services keep their subscribers, but they are not added to the index
+    fn get_users_to_backfill(&self, service_id: AddressId, service_index: usize) -> usize {
+        if !self.service_id().contains_id(service_id) {
+            return 0;
+        }
+
+        self.subscribed_users(service_id, service_index).len()
     }
 
     /// subscribe with the following arguments: service_id, service index
     #[endpoint]
     fn subscribe(&self, services: MultiValueEncoded<MultiValue2<AddressId, usize>>) {
+        self.require_not_paused();
+
         let caller = self.blockchain().get_caller();
         let caller_id = self.user_id().get_id_non_zero(&caller);
 
         for service in services {
             let (service_id, service_index) = service.into_tuple();
-            let service_options = self.service_info(service_id).get();
-            require!(
-                service_index < service_options.len(),
-                "Invalid service index"
-            );
-
-            let _ = self
-                .subscribed_users(service_id, service_index)
-                .insert(caller_id);
+            let _ = self.add_user_subscription(caller_id, service_id, service_index);
         }
     }
 
@@ -178,12 +573,196 @@ pub trait ServiceModule:
 
         for service in services {
             let (service_id, service_index) = service.into_tuple();
+            self.record_prorated_refund(caller_id, service_id, service_index);
+            if self.remove_user_subscription(caller_id, service_id, service_index) {
+                self.emit_unsubscribe_event(caller_id, service_id, service_index);
+            }
+        }
+    }
 
-            let _ = self
-                .subscribed_users(service_id, service_index)
-                .swap_remove(&caller_id);
-            self.user_next_payment_epoch(caller_id, service_id, service_index)
-                .clear();
+    fn add_pending_service_options(
+        &self,
+        service_address: ManagedAddress,
+        services: ManagedVec<ServiceInfo<Self::Api>>,
+        labels: ManagedVec<ManagedBuffer>,
+    ) {
+        require!(
+            !self.is_service_address_used(&service_address),
+            "Service already registered"
+        );
+
+        self.pending_service_info(&service_address)
+            .update(|existing_services| {
+                existing_services.extend(services.iter());
+                require!(
+                    existing_services.len() <= MAX_SERVICES_LENGTH,
+                    "Maximum services length reached"
+                );
+            });
+        self.pending_service_option_labels(&service_address)
+            .update(|existing_labels| existing_labels.append_vec(labels));
+        let _ = self.pending_services().insert(service_address.clone());
+        self.emit_register_service_event(service_address, services);
+    }
+
+    fn remove_service(&self, service_address: &ManagedAddress) {
+        let service_id = self.get_service_id(service_address);
+        if service_id != NULL_ID {
+            // before the profile is cleared, as it holds the owner
+            self.keep_unclaimed_service_revenue(service_id);
+            self.clear_service_profile(service_id);
+            self.service_info(service_id).clear();
+            self.moved_service_id(service_address).clear();
+            self.moved_service_address(service_id).clear();
+            let _ = self.service_id().remove_by_id(service_id);
+        }
+
+        let _ = self.pending_services().swap_remove(service_address);
+        self.pending_service_info(service_address).clear();
+        self.pending_service_metadata(service_address).clear();
+        self.pending_service_option_labels(service_address).clear();
+    }
+
+    fn record_prorated_refund(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        service_index: usize,
+    ) {
+        let last_charge_mapper = self.last_charge(user_id, service_id, service_index);
+        if !self
+            .prorated_refunds_enabled(service_id, service_index)
+            .get()
+            || last_charge_mapper.is_empty()
+        {
+            return;
+        }
+
+        let current_epoch = self.blockchain().get_block_epoch();
+        let next_payment_epoch = self
+            .user_next_payment_epoch(user_id, service_id, service_index)
+            .get();
+        if next_payment_epoch <= current_epoch {
+            return;
+        }
+
+        let last_charge = last_charge_mapper.get();
+        let cycle_epochs = next_payment_epoch - last_charge.epoch;
+        let remaining_epochs = next_payment_epoch - current_epoch;
+        let refund_amount = last_charge.payment.amount * remaining_epochs / cycle_epochs;
+        if refund_amount == 0 {
+            return;
+        }
+
+        let refund = EsdtTokenPayment::new(last_charge.payment.token_identifier, 0, refund_amount);
+        self.add_user_payment(refund, self.pending_refunds(user_id, service_id));
+        let _ = self.users_with_pending_refunds(service_id).insert(user_id);
+    }
+
+    fn build_service_info(
+        &self,
+        opt_payment_token: Option<TokenIdentifier>,
+        amount: BigUint,
+        payment_in_stable: bool,
+        subscription_epochs: Epoch,
+    ) -> ServiceInfo<Self::Api> {
+        require!(subscription_epochs > 0, "Subscription epochs must be > 0");
+        if let Some(token_id) = &opt_payment_token {
+            require!(
+                self.accepted_fees_tokens().contains(token_id),
+                "Invalid token ID"
+            );
+        }
+
+        ServiceInfo {
+            opt_payment_token,
+            amount,
+            payment_in_stable,
+            subscription_epochs,
         }
     }
+
+    /// Returns false if the user was already subscribed
+    fn add_user_subscription(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        service_index: usize,
+    ) -> bool {
+        let service_options = self.service_info(service_id).get();
+        require!(
+            service_index < service_options.len(),
+            "Invalid service index"
+        );
+
+        let _ = self.user_subscriptions(user_id).insert(UserSubscription {
+            service_id,
+            service_index,
+        });
+
+        let is_new_subscriber = self
+            .subscribed_users(service_id, service_index)
+            .insert(user_id);
+        if is_new_subscriber {
+            require!(
+                !self
+                    .deprecated_service_option(service_id, service_index)
+                    .get(),
+                "Service option deprecated"
+            );
+
+            let option_version = self.service_option_version(service_id, service_index).get();
+            self.user_option_version(user_id, service_id, service_index)
+                .set(option_version);
+            self.try_start_trial(user_id, service_id, service_index);
+            self.try_escrow_next_cycle(user_id, service_id, service_index);
+            self.emit_subscribe_event(user_id, service_id, service_index);
+        }
+
+        is_new_subscriber
+    }
+
+    fn try_start_trial(&self, user_id: AddressId, service_id: AddressId, service_index: usize) {
+        let trial_epochs = self.service_trial_epochs(service_id, service_index).get();
+        let used_trial_mapper = self.used_trial(user_id, service_id, service_index);
+        if trial_epochs == 0 || used_trial_mapper.get() {
+            return;
+        }
+
+        let current_epoch = self.blockchain().get_block_epoch();
+        self.user_next_payment_epoch(user_id, service_id, service_index)
+            .set(current_epoch + trial_epochs);
+        used_trial_mapper.set(true);
+    }
+
+    /// Returns false if the user was not subscribed
+    fn remove_user_subscription(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        service_index: usize,
+    ) -> bool {
+        self.release_escrow(user_id, service_id, service_index);
+
+        let was_subscribed = self
+            .subscribed_users(service_id, service_index)
+            .swap_remove(&user_id);
+        let _ = self
+            .user_subscriptions(user_id)
+            .swap_remove(&UserSubscription {
+                service_id,
+                service_index,
+            });
+        self.user_next_payment_epoch(user_id, service_id, service_index)
+            .clear();
+        self.failed_charge_attempts(user_id, service_id, service_index)
+            .clear();
+        self.past_due_epoch(user_id, service_id, service_index)
+            .clear();
+        self.last_charge(user_id, service_id, service_index).clear();
+        self.user_option_version(user_id, service_id, service_index)
+            .clear();
+
+        was_subscribed
+    }
 }
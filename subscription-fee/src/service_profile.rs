@@ -0,0 +1,264 @@
+multiversx_sc::imports!();
+multiversx_sc::derive_imports!();
+
+pub const MAX_SERVICE_NAME_LENGTH: usize = 64;
+pub const MAX_SERVICE_DESCRIPTION_HASH_LENGTH: usize = 64;
+pub const MAX_SERVICE_URL_LENGTH: usize = 256;
+pub const MAX_SERVICE_CATEGORY_LENGTH: usize = 32;
+pub const MAX_SERVICE_OPTION_LABEL_LENGTH: usize = 64;
+
+/// The description is kept off-chain, only its hash is stored. url is the website of the service.
+#[derive(TypeAbi, TopEncode, TopDecode, NestedEncode, NestedDecode, Clone)]
+pub struct ServiceMetadata<M: ManagedTypeApi> {
+    pub name: ManagedBuffer<M>,
+    pub description_hash: ManagedBuffer<M>,
+    pub url: ManagedBuffer<M>,
+    pub category: ManagedBuffer<M>,
+}
+
+#[derive(TypeAbi, TopEncode, TopDecode, NestedEncode, NestedDecode, Clone)]
+pub struct ServiceProfile<M: ManagedTypeApi> {
+    pub owner: ManagedAddress<M>,
+    pub payout_address: ManagedAddress<M>,
+    pub metadata: ServiceMetadata<M>,
+}
+
+#[multiversx_sc::module]
+pub trait ServiceProfileModule:
+    crate::common_storage::CommonStorageModule + crate::events::EventsModule
+{
+    /// The charged tokens of the service are sent to the payout address instead of the service address
+    #[endpoint(setServicePayoutAddress)]
+    fn set_service_payout_address(&self, service_id: AddressId, payout_address: ManagedAddress) {
+        require!(!payout_address.is_zero(), "Invalid payout address");
+
+        let mut profile = self.require_service_owner(service_id);
+        profile.payout_address = payout_address;
+        self.service_profile(service_id).set(profile);
+    }
+
+    #[endpoint(setServiceMetadata)]
+    fn set_service_metadata(
+        &self,
+        service_id: AddressId,
+        name: ManagedBuffer,
+        description_hash: ManagedBuffer,
+        url: ManagedBuffer,
+        category: ManagedBuffer,
+    ) {
+        let mut profile = self.require_service_owner(service_id);
+        profile.metadata = self.build_service_metadata(name, description_hash, url, category);
+        self.service_profile(service_id).set(profile);
+    }
+
+    /// Labels are shown to users instead of the option index. An empty label removes it.
+    #[endpoint(setServiceOptionLabel)]
+    fn set_service_option_label(
+        &self,
+        service_id: AddressId,
+        service_index: usize,
+        label: ManagedBuffer,
+    ) {
+        let _ = self.require_service_owner(service_id);
+        let service_options = self.service_info(service_id).get();
+        require!(
+            service_index < service_options.len(),
+            "Invalid service index"
+        );
+        require!(
+            label.len() <= MAX_SERVICE_OPTION_LABEL_LENGTH,
+            "Label too long"
+        );
+
+        if label.is_empty() {
+            self.service_option_label(service_id, service_index).clear();
+        } else {
+            self.service_option_label(service_id, service_index)
+                .set(label);
+        }
+    }
+
+    /// First step of the ownership transfer. The new owner has to call acceptServiceOwnership.
+    #[endpoint(transferServiceOwnership)]
+    fn transfer_service_ownership(&self, service_id: AddressId, new_owner: ManagedAddress) {
+        let _ = self.require_service_owner(service_id);
+        require!(!new_owner.is_zero(), "Invalid owner address");
+
+        self.pending_service_owner(service_id).set(new_owner);
+    }
+
+    #[endpoint(acceptServiceOwnership)]
+    fn accept_service_ownership(&self, service_id: AddressId) {
+        let pending_owner_mapper = self.pending_service_owner(service_id);
+        require!(
+            !pending_owner_mapper.is_empty(),
+            "No pending ownership transfer"
+        );
+
+        let caller = self.blockchain().get_caller();
+        require!(
+            pending_owner_mapper.get() == caller,
+            "Not the pending owner"
+        );
+
+        let mut profile = self.get_service_profile(service_id);
+        let previous_owner = core::mem::replace(&mut profile.owner, caller.clone());
+        self.service_profile(service_id).set(profile);
+        pending_owner_mapper.clear();
+
+        self.emit_service_ownership_transferred_event(service_id, previous_owner, caller);
+    }
+
+    /// Moves the service to a new address. The service ID is kept, along with its options, settings and subscribers.
+    #[endpoint(changeServiceAddress)]
+    fn change_service_address(&self, service_id: AddressId, new_address: ManagedAddress) {
+        let _ = self.require_service_owner(service_id);
+        require!(!new_address.is_zero(), "Invalid service address");
+
+        // a service can move back to its original address, which is still mapped to its ID
+        let original_service_id = self.service_id().get_id(&new_address);
+        require!(
+            (original_service_id == NULL_ID || original_service_id == service_id)
+                && self.moved_service_id(&new_address).is_empty()
+                && !self.pending_services().contains(&new_address),
+            "Address already used by a service"
+        );
+
+        let opt_previous_address = self.get_service_address(service_id);
+        require!(opt_previous_address.is_some(), "Unknown service");
+
+        let previous_address = unsafe { opt_previous_address.unwrap_unchecked() };
+        self.moved_service_id(&previous_address).clear();
+        if original_service_id == service_id {
+            self.moved_service_address(service_id).clear();
+        } else {
+            self.moved_service_id(&new_address).set(service_id);
+            self.moved_service_address(service_id).set(&new_address);
+        }
+
+        self.emit_service_address_changed_event(service_id, previous_address, new_address);
+    }
+
+    /// Returns NULL_ID if the address is not the current address of an approved service
+    fn get_service_id(&self, service_address: &ManagedAddress) -> AddressId {
+        let moved_service_id_mapper = self.moved_service_id(service_address);
+        if !moved_service_id_mapper.is_empty() {
+            return moved_service_id_mapper.get();
+        }
+
+        // the original address of a service that moved no longer belongs to it
+        let service_id = self.service_id().get_id(service_address);
+        if service_id != NULL_ID && !self.moved_service_address(service_id).is_empty() {
+            return NULL_ID;
+        }
+
+        service_id
+    }
+
+    fn get_service_id_non_zero(&self, service_address: &ManagedAddress) -> AddressId {
+        let service_id = self.get_service_id(service_address);
+        require!(service_id != NULL_ID, "Unknown address");
+
+        service_id
+    }
+
+    #[view(getServiceAddress)]
+    fn get_service_address(&self, service_id: AddressId) -> Option<ManagedAddress> {
+        let moved_address_mapper = self.moved_service_address(service_id);
+        if !moved_address_mapper.is_empty() {
+            return Some(moved_address_mapper.get());
+        }
+
+        self.service_id().get_address(service_id)
+    }
+
+    /// Includes the original address of a service that moved, which stays mapped to its ID
+    fn is_service_address_used(&self, service_address: &ManagedAddress) -> bool {
+        self.service_id().get_id(service_address) != NULL_ID
+            || !self.moved_service_id(service_address).is_empty()
+    }
+
+    /// Services approved before profiles were added are owned by, and paid to, the service address
+    #[view(getServiceProfile)]
+    fn get_service_profile(&self, service_id: AddressId) -> ServiceProfile<Self::Api> {
+        let profile_mapper = self.service_profile(service_id);
+        if !profile_mapper.is_empty() {
+            return profile_mapper.get();
+        }
+
+        let opt_service_address = self.get_service_address(service_id);
+        require!(opt_service_address.is_some(), "Unknown service");
+
+        let service_address = unsafe { opt_service_address.unwrap_unchecked() };
+        self.new_service_profile(service_address, self.empty_service_metadata())
+    }
+
+    fn new_service_profile(
+        &self,
+        service_address: ManagedAddress,
+        metadata: ServiceMetadata<Self::Api>,
+    ) -> ServiceProfile<Self::Api> {
+        ServiceProfile {
+            owner: service_address.clone(),
+            payout_address: service_address,
+            metadata,
+        }
+    }
+
+    fn build_service_metadata(
+        &self,
+        name: ManagedBuffer,
+        description_hash: ManagedBuffer,
+        url: ManagedBuffer,
+        category: ManagedBuffer,
+    ) -> ServiceMetadata<Self::Api> {
+        require!(name.len() <= MAX_SERVICE_NAME_LENGTH, "Name too long");
+        require!(
+            description_hash.len() <= MAX_SERVICE_DESCRIPTION_HASH_LENGTH,
+            "Description hash too long"
+        );
+        require!(url.len() <= MAX_SERVICE_URL_LENGTH, "URL too long");
+        require!(
+            category.len() <= MAX_SERVICE_CATEGORY_LENGTH,
+            "Category too long"
+        );
+
+        ServiceMetadata {
+            name,
+            description_hash,
+            url,
+            category,
+        }
+    }
+
+    fn empty_service_metadata(&self) -> ServiceMetadata<Self::Api> {
+        ServiceMetadata {
+            name: ManagedBuffer::new(),
+            description_hash: ManagedBuffer::new(),
+            url: ManagedBuffer::new(),
+            category: ManagedBuffer::new(),
+        }
+    }
+
+    fn require_service_owner(&self, service_id: AddressId) -> ServiceProfile<Self::Api> {
+        let profile = self.get_service_profile(service_id);
+        let caller = self.blockchain().get_caller();
+        require!(
+            profile.owner == caller,
+            "Only the service owner can call this"
+        );
+
+        profile
+    }
+
+    /// Must be called before the service options are cleared, to remove their labels
+    fn clear_service_profile(&self, service_id: AddressId) {
+        self.service_profile(service_id).clear();
+        self.pending_service_owner(service_id).clear();
+
+        let service_options_len = self.service_info(service_id).get().len();
+        for service_index in 0..service_options_len {
+            self.service_option_label(service_id, service_index).clear();
+        }
+    }
+}
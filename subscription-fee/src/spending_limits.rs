@@ -0,0 +1,156 @@
+multiversx_sc::imports!();
+multiversx_sc::derive_imports!();
+
+use common_structs::SubtractError;
+
+use crate::subtract_payments::Epoch;
+
+#[derive(TypeAbi, TopEncode, TopDecode, NestedEncode, NestedDecode, Clone)]
+pub struct SpendingCap<M: ManagedTypeApi> {
+    pub max_amount: BigUint<M>,
+    pub period_epochs: Epoch,
+}
+
+#[derive(TypeAbi, TopEncode, TopDecode, NestedEncode, NestedDecode, Clone)]
+pub struct PeriodSpending<M: ManagedTypeApi> {
+    pub period_start: Epoch,
+    pub spent: BigUint<M>,
+}
+
+#[multiversx_sc::module]
+pub trait SpendingLimitsModule: crate::common_storage::CommonStorageModule {
+    /// Limits the amount of the given token the service can charge the caller within each period of period_epochs
+    #[endpoint(setSpendingCap)]
+    fn set_spending_cap(
+        &self,
+        service_id: AddressId,
+        token_id: TokenIdentifier,
+        max_amount: BigUint,
+        period_epochs: Epoch,
+    ) {
+        require!(token_id.is_valid_esdt_identifier(), "Invalid token ID");
+        require!(period_epochs > 0, "Invalid period");
+
+        let user_id = self.get_caller_user_id_for_service(service_id);
+        self.spending_cap(user_id, service_id, &token_id)
+            .set(SpendingCap {
+                max_amount,
+                period_epochs,
+            });
+        self.spending_in_period(user_id, service_id, &token_id)
+            .clear();
+    }
+
+    #[endpoint(removeSpendingCap)]
+    fn remove_spending_cap(&self, service_id: AddressId, token_id: TokenIdentifier) {
+        let user_id = self.get_caller_user_id_for_service(service_id);
+        self.spending_cap(user_id, service_id, &token_id).clear();
+        self.spending_in_period(user_id, service_id, &token_id)
+            .clear();
+    }
+
+    /// Restricts the tokens the service can charge the caller with. Providing no tokens removes the restriction.
+    #[endpoint(setServiceTokenWhitelist)]
+    fn set_service_token_whitelist(
+        &self,
+        service_id: AddressId,
+        tokens: MultiValueEncoded<TokenIdentifier>,
+    ) {
+        let user_id = self.get_caller_user_id_for_service(service_id);
+        let mut whitelist_mapper = self.service_token_whitelist(user_id, service_id);
+        whitelist_mapper.clear();
+        for token_id in tokens {
+            require!(token_id.is_valid_esdt_identifier(), "Invalid token ID");
+
+            let _ = whitelist_mapper.insert(token_id);
+        }
+    }
+
+    fn get_caller_user_id_for_service(&self, service_id: AddressId) -> AddressId {
+        require!(self.service_id().contains_id(service_id), "Unknown service");
+
+        let caller = self.blockchain().get_caller();
+        self.user_id().get_id_non_zero(&caller)
+    }
+
+    fn check_spending_limits(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        payment: &EsdtTokenPayment,
+    ) -> Result<(), SubtractError> {
+        let whitelist_mapper = self.service_token_whitelist(user_id, service_id);
+        if !whitelist_mapper.is_empty() && !whitelist_mapper.contains(&payment.token_identifier) {
+            return Result::Err(SubtractError::TokenNotAllowed);
+        }
+
+        let cap_mapper = self.spending_cap(user_id, service_id, &payment.token_identifier);
+        if cap_mapper.is_empty() {
+            return Result::Ok(());
+        }
+
+        let cap = cap_mapper.get();
+        let spent = self.get_current_period_spending(user_id, service_id, payment, &cap);
+        if spent + &payment.amount > cap.max_amount {
+            return Result::Err(SubtractError::SpendingCapExceeded);
+        }
+
+        Result::Ok(())
+    }
+
+    fn record_spending(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        payment: &EsdtTokenPayment,
+    ) {
+        let cap_mapper = self.spending_cap(user_id, service_id, &payment.token_identifier);
+        if cap_mapper.is_empty() {
+            return;
+        }
+
+        let cap = cap_mapper.get();
+        let spending_mapper =
+            self.spending_in_period(user_id, service_id, &payment.token_identifier);
+        let current_epoch = self.blockchain().get_block_epoch();
+        let period_spending = if spending_mapper.is_empty() {
+            PeriodSpending {
+                period_start: current_epoch,
+                spent: payment.amount.clone(),
+            }
+        } else {
+            let mut period_spending = spending_mapper.get();
+            if current_epoch >= period_spending.period_start + cap.period_epochs {
+                period_spending.period_start = current_epoch;
+                period_spending.spent = BigUint::zero();
+            }
+
+            period_spending.spent += &payment.amount;
+            period_spending
+        };
+
+        spending_mapper.set(period_spending);
+    }
+
+    fn get_current_period_spending(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        payment: &EsdtTokenPayment,
+        cap: &SpendingCap<Self::Api>,
+    ) -> BigUint {
+        let spending_mapper =
+            self.spending_in_period(user_id, service_id, &payment.token_identifier);
+        if spending_mapper.is_empty() {
+            return BigUint::zero();
+        }
+
+        let period_spending = spending_mapper.get();
+        let current_epoch = self.blockchain().get_block_epoch();
+        if current_epoch >= period_spending.period_start + cap.period_epochs {
+            return BigUint::zero();
+        }
+
+        period_spending.spent
+    }
+}
@@ -3,8 +3,22 @@ multiversx_sc::derive_imports!();
 
 use core::hint::unreachable_unchecked;
 
+pub use common_structs::SubtractError;
+
+use common_structs::UniquePayments;
+use multiversx_sc_modules::ongoing_operation::{
+    CONTINUE_OP, DEFAULT_MIN_GAS_TO_SAVE_PROGRESS, STOP_OP,
+};
+
+use crate::{
+    pair_actions::MAX_PERCENTAGE,
+    service::{LastCharge, ServiceInfo, UserSubscription},
+};
+
 pub type Epoch = u64;
 
+pub const MAX_DUE_SUBSCRIPTIONS_PAGE_SIZE: usize = 100;
+
 #[must_use]
 #[derive(Debug, PartialEq, Eq, Clone, TopEncode, TopDecode, TypeAbi)]
 pub enum ScResult<
@@ -33,134 +47,661 @@ impl<T: NestedEncode + NestedDecode + TypeAbi, E: NestedEncode + NestedDecode +
     }
 }
 
+#[derive(TypeAbi, TopEncode, TopDecode)]
+pub struct SubtractBatchProgress<M: ManagedTypeApi> {
+    pub args_hash: ManagedByteArray<M, 32>,
+    pub processed: usize,
+}
+
 #[multiversx_sc::module]
 pub trait SubtractPaymentsModule:
     crate::fees::FeesModule
     + crate::service::ServiceModule
     + crate::pair_actions::PairActionsModule
     + crate::common_storage::CommonStorageModule
+    + crate::events::EventsModule
     + multiversx_sc_modules::ongoing_operation::OngoingOperationModule
+    + multiversx_sc_modules::pause::PauseModule
+    + energy_query::EnergyQueryModule
+    + crate::spending_limits::SpendingLimitsModule
+    + crate::auto_swap::AutoSwapModule
+    + crate::escrow::EscrowModule
+    + crate::revenue::RevenueModule
+    + crate::service_profile::ServiceProfileModule
 {
+    /// Deposits the payments and subscribes the caller to the given services, by providing the service_id and service indexes.
+    /// For services with automatic billing, the first cycle of each subscription is charged immediately and sent to the service,
+    /// unless it is a free trial. Other services charge it themselves through subtractPayment.
+    #[payable("*")]
+    #[endpoint(subscribeAndDeposit)]
+    fn subscribe_and_deposit(
+        &self,
+        services: MultiValueEncoded<MultiValue2<AddressId, usize>>,
+    ) -> ManagedVec<EsdtTokenPayment> {
+        self.require_not_paused();
+        require!(!services.is_empty(), "No arguments provided");
+
+        let payments = self.get_deposit_payments();
+        let caller = self.blockchain().get_caller();
+        let caller_id = self.deposit_user_payments(&caller, payments);
+        let current_epoch = self.blockchain().get_block_epoch();
+
+        let mut charged_payments = ManagedVec::new();
+        for service in services {
+            let (service_id, service_index) = service.into_tuple();
+            require!(self.service_id().contains_id(service_id), "Unknown service");
+            require!(
+                self.add_user_subscription(caller_id, service_id, service_index),
+                "Already subscribed"
+            );
+
+            if !self.automatic_billing_enabled(service_id).get() {
+                continue;
+            }
+
+            // the first cycle is covered by the free trial, if the service offers one
+            let next_payment_epoch = self
+                .user_next_payment_epoch(caller_id, service_id, service_index)
+                .get();
+            if next_payment_epoch > current_epoch {
+                continue;
+            }
+
+            let subtract_result =
+                self.process_user_payment(service_id, service_index, caller_id, current_epoch);
+            require!(!subtract_result.is_err(), "Could not charge first cycle");
+
+            let payment = unsafe { subtract_result.unwrap_unchecked() };
+            self.send_service_revenue(service_id, &ManagedVec::from_single_item(payment.clone()));
+
+            charged_payments.push(payment);
+        }
+
+        charged_payments
+    }
+
+    /// Unsubscribes the caller from all services and withdraws all the deposited tokens
+    #[endpoint(unsubscribeAll)]
+    fn unsubscribe_all(&self) -> ManagedVec<EsdtTokenPayment> {
+        let caller = self.blockchain().get_caller();
+        let caller_id = self.user_id().get_id_non_zero(&caller);
+
+        let user_subscriptions: ManagedVec<UserSubscription> =
+            self.user_subscriptions(caller_id).iter().collect();
+        for subscription in user_subscriptions.iter() {
+            self.record_prorated_refund(
+                caller_id,
+                subscription.service_id,
+                subscription.service_index,
+            );
+            if self.remove_user_subscription(
+                caller_id,
+                subscription.service_id,
+                subscription.service_index,
+            ) {
+                self.emit_unsubscribe_event(
+                    caller_id,
+                    subscription.service_id,
+                    subscription.service_index,
+                );
+            }
+        }
+
+        let user_fees_mapper = self.user_deposited_fees(caller_id);
+        if user_fees_mapper.is_empty() {
+            return ManagedVec::new();
+        }
+
+        // tokens fully spent on charges are kept with a zero amount
+        let mut output_payments = ManagedVec::new();
+        for payment in user_fees_mapper.take().into_payments().iter() {
+            if payment.amount > 0 {
+                output_payments.push(payment);
+            }
+        }
+
+        if !output_payments.is_empty() {
+            self.send().direct_multi(&caller, &output_payments);
+            self.emit_withdraw_event(caller_id, output_payments.clone());
+        }
+
+        output_payments
+    }
+
     #[endpoint(subtractPayment)]
     fn subtract_payment(
         &self,
         service_index: usize,
         user_id: AddressId,
-    ) -> ScResult<EsdtTokenPayment, ()> {
+    ) -> ScResult<EsdtTokenPayment, SubtractError> {
+        self.require_not_paused();
+
+        let caller = self.blockchain().get_caller();
+        let service_id = self.get_service_id_non_zero(&caller);
+        let current_epoch = self.blockchain().get_block_epoch();
+
+        // users that are not due yet, like those in a free trial, get a NotDueYet error instead of failing the call
+        let subtract_result =
+            self.process_user_payment(service_id, service_index, user_id, current_epoch);
+        if let ScResult::Ok(payment) = &subtract_result {
+            self.send_service_revenue(service_id, &ManagedVec::from_single_item(payment.clone()));
+        }
+
+        subtract_result
+    }
+
+    /// Arguments are pairs of service_index and user_id.
+    /// Users that cannot be charged get a ScResult::Err with the reason, instead of failing the whole batch.
+    /// If the operation runs out of gas, calling again with the same arguments resumes from where it stopped.
+    /// Returns the completion status, the index of the first processed argument, which is not 0 when resuming,
+    /// and the results of the processed arguments.
+    /// All the collected tokens are sent to the service in a single transfer, unless it uses revenue pull mode.
+    #[endpoint(subtractPaymentBatch)]
+    fn subtract_payment_batch(
+        &self,
+        args: MultiValueEncoded<MultiValue2<usize, AddressId>>,
+    ) -> MultiValue3<
+        OperationCompletionStatus,
+        usize,
+        MultiValueEncoded<ScResult<EsdtTokenPayment, SubtractError>>,
+    > {
+        self.require_not_paused();
+
         let caller = self.blockchain().get_caller();
-        let service_id = self.service_id().get_id_non_zero(&caller);
+        let service_id = self.get_service_id_non_zero(&caller);
+        let current_epoch = self.blockchain().get_block_epoch();
+
+        // progress is only kept for the exact same arguments, so a different batch always starts from the beginning
+        let args_hash = self.get_batch_args_hash(&args);
+        let progress_mapper = self.subtract_batch_progress(service_id);
+        let mut progress = SubtractBatchProgress {
+            args_hash,
+            processed: 0,
+        };
+        if !progress_mapper.is_empty() {
+            let saved_progress = progress_mapper.get();
+            if saved_progress.args_hash == progress.args_hash {
+                progress.processed = saved_progress.processed;
+            }
+        }
+
+        let start_index = progress.processed;
+        let mut args_iter = args.into_iter().skip(start_index);
+        let mut collected_payments = UniquePayments::new();
+        let mut results = MultiValueEncoded::new();
+        let run_result = self.run_while_it_has_gas(DEFAULT_MIN_GAS_TO_SAVE_PROGRESS, || {
+            let (service_index, user_id) = match args_iter.next() {
+                Some(arg) => arg.into_tuple(),
+                None => return STOP_OP,
+            };
+
+            let subtract_result =
+                self.process_user_payment(service_id, service_index, user_id, current_epoch);
+            if let ScResult::Ok(payment) = &subtract_result {
+                collected_payments.add_payment(payment.clone());
+            }
+
+            results.push(subtract_result);
+            progress.processed += 1;
+
+            CONTINUE_OP
+        });
+
+        if run_result == OperationCompletionStatus::InterruptedBeforeOutOfGas {
+            progress_mapper.set(progress);
+        } else {
+            progress_mapper.clear();
+        }
+
+        let payments = collected_payments.into_payments();
+        self.send_service_revenue(service_id, &payments);
+
+        (run_result, start_index, results).into()
+    }
+
+    /// Anyone can call this to charge the users of a service option whose next payment epoch has arrived,
+    /// if the service enabled automatic billing.
+    /// Subscribers are walked in pages of page_size, each call continuing from where the previous one stopped.
+    /// The collected tokens are sent to the service, unless it uses revenue pull mode.
+    #[endpoint(processDueSubscriptions)]
+    fn process_due_subscriptions(
+        &self,
+        service_id: AddressId,
+        service_index: usize,
+        page_size: usize,
+    ) -> ManagedVec<EsdtTokenPayment> {
+        self.require_not_paused();
+        require!(
+            page_size > 0 && page_size <= MAX_DUE_SUBSCRIPTIONS_PAGE_SIZE,
+            "Invalid page size"
+        );
+
+        require!(self.service_id().contains_id(service_id), "Unknown service");
+        require!(
+            self.automatic_billing_enabled(service_id).get(),
+            "Automatic billing not enabled"
+        );
+
+        let service_options = self.service_info(service_id).get();
+        require!(
+            service_index < service_options.len(),
+            "Invalid service index"
+        );
+
         let current_epoch = self.blockchain().get_block_epoch();
+        let subscribed_users_mapper = self.subscribed_users(service_id, service_index);
+        let cursor_mapper = self.due_subscriptions_cursor(service_id, service_index);
+        let mut index = cursor_mapper.get();
+        if index >= subscribed_users_mapper.len() {
+            index = 0;
+        }
 
+        let mut processed_users = 0;
+        let mut collected_payments = UniquePayments::new();
+        while processed_users < page_size && index < subscribed_users_mapper.len() {
+            let user_id = subscribed_users_mapper.get_by_index(index + 1);
+            let subtract_result =
+                self.process_user_payment(service_id, service_index, user_id, current_epoch);
+            if let ScResult::Ok(payment) = subtract_result {
+                collected_payments.add_payment(payment);
+            }
+
+            processed_users += 1;
+
+            // an auto-unsubscribe moves the last subscriber into this index, so it is processed next
+            if subscribed_users_mapper.contains(&user_id) {
+                index += 1;
+            }
+        }
+
+        if index < subscribed_users_mapper.len() {
+            cursor_mapper.set(index);
+        } else {
+            cursor_mapper.clear();
+        }
+
+        let payments = collected_payments.into_payments();
+        self.send_service_revenue(service_id, &payments);
+
+        payments
+    }
+
+    fn get_batch_args_hash(
+        &self,
+        args: &MultiValueEncoded<MultiValue2<usize, AddressId>>,
+    ) -> ManagedByteArray<Self::Api, 32> {
+        let mut serialized_args = ManagedBuffer::new();
+        for arg in args.clone() {
+            let (service_index, user_id) = arg.into_tuple();
+            let _ = service_index.dep_encode(&mut serialized_args);
+            let _ = user_id.dep_encode(&mut serialized_args);
+        }
+
+        self.crypto().keccak256(&serialized_args)
+    }
+
+    fn process_user_payment(
+        &self,
+        service_id: AddressId,
+        service_index: usize,
+        user_id: AddressId,
+        current_epoch: Epoch,
+    ) -> ScResult<EsdtTokenPayment, SubtractError> {
         if !self
             .subscribed_users(service_id, service_index)
             .contains(&user_id)
         {
-            return ScResult::Err(());
+            return ScResult::Err(SubtractError::NotSubscribed);
         }
 
         let next_payment_mapper = self.user_next_payment_epoch(user_id, service_id, service_index);
-        let next_payment_epoch = next_payment_mapper.get();
-        require!(next_payment_epoch <= current_epoch, "Cannot subtract yet");
+        if next_payment_mapper.get() > current_epoch {
+            return ScResult::Err(SubtractError::NotDueYet);
+        }
 
         let service_info = self.service_info(service_id).get().get(service_index);
         let subscription_epochs = service_info.subscription_epochs;
 
         if subscription_epochs == 0 {
-            return ScResult::Err(());
+            return ScResult::Err(SubtractError::InvalidServiceInfo);
         }
 
         let opt_user_address = self.user_id().get_address(user_id);
         if opt_user_address.is_none() {
-            return ScResult::Err(());
+            return ScResult::Err(SubtractError::UserUnknown);
         }
 
-        let subtract_result = match service_info.opt_payment_token {
+        let user_address = unsafe { opt_user_address.unwrap_unchecked() };
+        self.release_outdated_escrow(user_id, service_id, service_index, &service_info);
+
+        let escrow_mapper = self.cycle_escrow(user_id, service_id, service_index);
+        let subtract_result = if !escrow_mapper.is_empty() {
+            self.subtract_from_escrow(user_id, service_id, service_index, &user_address)
+        } else {
+            self.subtract_from_deposits(
+                user_id,
+                service_id,
+                service_index,
+                &user_address,
+                service_info,
+            )
+        };
+        let charged_payment = match subtract_result {
+            ScResult::Ok(payment) => payment,
+            ScResult::Err(err) => {
+                // users are not made past due for failures they cannot fix, like unavailable prices
+                if err.is_balance_error() {
+                    self.handle_failed_charge(user_id, service_id, service_index, current_epoch);
+                }
+
+                return ScResult::Err(err);
+            }
+        };
+
+        next_payment_mapper.set(current_epoch + subscription_epochs);
+        self.handle_successful_charge(user_id, service_id, service_index);
+        self.update_user_option_version(user_id, service_id, service_index);
+
+        // the service receives the charged payment minus the protocol fee
+        let mut net_payment = charged_payment;
+        let opt_fee_payment = self.take_protocol_fee(&mut net_payment);
+        self.emit_subtract_payment_event(user_id, service_id, service_index, net_payment.clone());
+        if let Some(fee_payment) = opt_fee_payment {
+            self.emit_protocol_fee_event(user_id, service_id, service_index, fee_payment);
+        }
+        self.add_lifetime_revenue(service_id, net_payment.clone());
+
+        if self
+            .prorated_refunds_enabled(service_id, service_index)
+            .get()
+        {
+            self.last_charge(user_id, service_id, service_index)
+                .set(LastCharge {
+                    payment: net_payment.clone(),
+                    epoch: current_epoch,
+                });
+        }
+
+        self.try_escrow_next_cycle(user_id, service_id, service_index);
+
+        ScResult::Ok(net_payment)
+    }
+
+    /// The escrowed payment was computed when the cycle was reserved. Any energy discount is applied now,
+    /// and the discounted part goes back to the user's deposits.
+    fn subtract_from_escrow(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        service_index: usize,
+        user_address: &ManagedAddress,
+    ) -> ScResult<EsdtTokenPayment, SubtractError> {
+        let escrowed_payment = self.cycle_escrow(user_id, service_id, service_index).take();
+        let amount = self.apply_energy_discount(
+            user_address,
+            service_id,
+            service_index,
+            escrowed_payment.amount.clone(),
+        );
+        let payment = EsdtTokenPayment::new(escrowed_payment.token_identifier.clone(), 0, amount);
+        if let Result::Err(err) = self.check_spending_limits(user_id, service_id, &payment) {
+            self.return_escrowed_payment(user_id, service_id, service_index, escrowed_payment);
+
+            return ScResult::Err(err);
+        }
+
+        self.record_spending(user_id, service_id, &payment);
+
+        let surplus_amount = &escrowed_payment.amount - &payment.amount;
+        if surplus_amount > 0 {
+            let surplus =
+                EsdtTokenPayment::new(escrowed_payment.token_identifier, 0, surplus_amount);
+            self.return_escrowed_payment(user_id, service_id, service_index, surplus);
+        }
+
+        ScResult::Ok(payment)
+    }
+
+    fn subtract_from_deposits(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        service_index: usize,
+        user_address: &ManagedAddress,
+        service_info: ServiceInfo<Self::Api>,
+    ) -> ScResult<EsdtTokenPayment, SubtractError> {
+        let amount = self.apply_energy_discount(
+            user_address,
+            service_id,
+            service_index,
+            service_info.amount,
+        );
+        match service_info.opt_payment_token {
             Some(token_id) => {
-                if service_info.payment_in_stable {
-                    self.subtract_specific_token_in_stable(user_id, token_id, service_info.amount)
+                let payment_result = if service_info.payment_in_stable {
+                    self.get_payment_in_stable(token_id, amount)
                 } else {
-                    self.subtract_specific_token(user_id, token_id, service_info.amount)
+                    ScResult::Ok(EsdtTokenPayment::new(token_id, 0, amount))
+                };
+
+                match payment_result {
+                    ScResult::Ok(payment) => {
+                        self.subtract_specific_token(user_id, service_id, payment)
+                    }
+                    ScResult::Err(err) => ScResult::Err(err),
                 }
             }
-            None => self.subtract_any_token(user_id, service_info.amount),
-        };
-        if let ScResult::Ok(payment) = &subtract_result {
-            self.send().direct_esdt(
-                &caller,
-                &payment.token_identifier,
-                payment.token_nonce,
-                &payment.amount,
+            None => self.subtract_any_token(user_id, service_id, amount),
+        }
+    }
+
+    fn apply_energy_discount(
+        &self,
+        user_address: &ManagedAddress,
+        service_id: AddressId,
+        service_index: usize,
+        amount: BigUint,
+    ) -> BigUint {
+        let tiers_mapper = self.energy_discount_tiers(service_id, service_index);
+        if tiers_mapper.is_empty() || self.energy_factory_address().is_empty() {
+            return amount;
+        }
+
+        let user_energy = self.get_energy_amount(user_address);
+        let mut discount_percentage = 0;
+        for tier in tiers_mapper.get().iter() {
+            if user_energy < tier.min_energy {
+                break;
+            }
+
+            discount_percentage = tier.discount_percentage;
+        }
+
+        if discount_percentage == 0 {
+            return amount;
+        }
+
+        amount * (MAX_PERCENTAGE - discount_percentage) / MAX_PERCENTAGE
+    }
+
+    fn handle_failed_charge(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        service_index: usize,
+        current_epoch: Epoch,
+    ) {
+        let attempts_mapper = self.failed_charge_attempts(user_id, service_id, service_index);
+        let failed_attempts = attempts_mapper.get() + 1;
+
+        let past_due_mapper = self.past_due_epoch(user_id, service_id, service_index);
+        if past_due_mapper.is_empty() {
+            past_due_mapper.set(current_epoch);
+            self.emit_subscription_past_due_event(
+                user_id,
+                service_id,
+                service_index,
+                failed_attempts,
             );
+        }
+
+        let grace_period_mapper = self.service_grace_period(service_id);
+        if !grace_period_mapper.is_empty()
+            && current_epoch >= past_due_mapper.get() + grace_period_mapper.get()
+        {
+            let _ = self.remove_user_subscription(user_id, service_id, service_index);
+            self.emit_auto_unsubscribe_event(user_id, service_id, service_index, failed_attempts);
 
-            next_payment_mapper.set(current_epoch + subscription_epochs);
+            return;
         }
 
-        subtract_result
+        attempts_mapper.set(failed_attempts);
     }
 
-    fn subtract_specific_token(
+    fn handle_successful_charge(
         &self,
         user_id: AddressId,
-        token_id: TokenIdentifier,
-        amount: BigUint,
-    ) -> ScResult<EsdtTokenPayment, ()> {
-        let payment = EsdtTokenPayment::new(token_id, 0, amount);
-        let raw_result = self
-            .user_deposited_fees(user_id)
-            .update(|user_fees| user_fees.deduct_payment(&payment));
+        service_id: AddressId,
+        service_index: usize,
+    ) {
+        let past_due_mapper = self.past_due_epoch(user_id, service_id, service_index);
+        if past_due_mapper.is_empty() {
+            return;
+        }
 
-        match raw_result {
-            Result::Ok(()) => ScResult::Ok(payment),
-            Result::Err(()) => ScResult::Err(()),
+        past_due_mapper.clear();
+        self.failed_charge_attempts(user_id, service_id, service_index)
+            .clear();
+        self.emit_subscription_recovered_event(user_id, service_id, service_index);
+    }
+
+    fn update_user_option_version(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        service_index: usize,
+    ) {
+        let option_version = self.service_option_version(service_id, service_index).get();
+        let user_version_mapper = self.user_option_version(user_id, service_id, service_index);
+        let user_version = user_version_mapper.get();
+        if user_version == option_version {
+            return;
         }
+
+        user_version_mapper.set(option_version);
+        self.emit_subscription_migrated_event(
+            user_id,
+            service_id,
+            service_index,
+            user_version,
+            option_version,
+        );
+    }
+
+    /// The missing balance of the required token is swapped from the user's other tokens, if they enabled auto-swap
+    fn subtract_specific_token(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        payment: EsdtTokenPayment,
+    ) -> ScResult<EsdtTokenPayment, SubtractError> {
+        // checked before swapping, so no tokens are swapped for a charge that would be rejected
+        if let Result::Err(err) = self.check_spending_limits(user_id, service_id, &payment) {
+            return ScResult::Err(err);
+        }
+
+        self.cover_missing_balance_with_swap(user_id, service_id, &payment);
+        self.deduct_user_payment(user_id, service_id, payment)
     }
 
     fn subtract_specific_token_in_stable(
         &self,
         user_id: AddressId,
+        service_id: AddressId,
+        token_id: TokenIdentifier,
+        amount_in_stable_token: BigUint,
+    ) -> ScResult<EsdtTokenPayment, SubtractError> {
+        match self.get_payment_in_stable(token_id, amount_in_stable_token) {
+            ScResult::Ok(payment) => self.deduct_user_payment(user_id, service_id, payment),
+            ScResult::Err(err) => ScResult::Err(err),
+        }
+    }
+
+    fn get_payment_in_stable(
+        &self,
         token_id: TokenIdentifier,
         amount_in_stable_token: BigUint,
-    ) -> ScResult<EsdtTokenPayment, ()> {
+    ) -> ScResult<EsdtTokenPayment, SubtractError> {
         let query_result = self.get_worth_of_price(&token_id, amount_in_stable_token);
         if query_result.is_err() {
-            return ScResult::Err(());
+            return ScResult::Err(SubtractError::PriceQueryFailed);
         }
 
         let tokens_to_pay = unsafe { query_result.unwrap_unchecked() };
-        let payment_to_deduct = EsdtTokenPayment::new(token_id, 0, tokens_to_pay);
+        ScResult::Ok(EsdtTokenPayment::new(token_id, 0, tokens_to_pay))
+    }
+
+    fn deduct_user_payment(
+        &self,
+        user_id: AddressId,
+        service_id: AddressId,
+        payment: EsdtTokenPayment,
+    ) -> ScResult<EsdtTokenPayment, SubtractError> {
+        if let Result::Err(err) = self.check_spending_limits(user_id, service_id, &payment) {
+            return ScResult::Err(err);
+        }
+
         let raw_result = self
             .user_deposited_fees(user_id)
-            .update(|user_fees| user_fees.deduct_payment(&payment_to_deduct));
+            .update(|user_fees| user_fees.deduct_payment(&payment));
 
         match raw_result {
-            Result::Ok(()) => ScResult::Ok(payment_to_deduct),
-            Result::Err(()) => ScResult::Err(()),
+            Result::Ok(()) => {
+                self.record_spending(user_id, service_id, &payment);
+
+                ScResult::Ok(payment)
+            }
+            Result::Err(err) => ScResult::Err(err),
         }
     }
 
     fn subtract_any_token(
         &self,
         user_id: AddressId,
+        service_id: AddressId,
         amount_in_stable_token: BigUint,
-    ) -> ScResult<EsdtTokenPayment, ()> {
+    ) -> ScResult<EsdtTokenPayment, SubtractError> {
         let tokens_mapper = self.user_deposited_fees(user_id);
         if tokens_mapper.is_empty() {
-            return ScResult::Err(());
+            return ScResult::Err(SubtractError::NoDeposits);
         }
 
+        // reports the reason of the last failed token, if none could cover the payment
+        let mut last_error = SubtractError::NoDeposits;
         let user_tokens = tokens_mapper.get().into_payments();
-        for user_token in user_tokens.iter() {
+        let excluded_tokens_mapper = self.user_excluded_tokens(user_id);
+        for token_id in self
+            .get_user_payment_tokens_in_order(user_id, &user_tokens)
+            .iter()
+        {
+            if excluded_tokens_mapper.contains(&token_id) {
+                last_error = SubtractError::TokenNotAllowed;
+                continue;
+            }
+
             let subtract_result = self.subtract_specific_token_in_stable(
                 user_id,
-                user_token.token_identifier,
+                service_id,
+                (*token_id).clone(),
                 amount_in_stable_token.clone(),
             );
 
-            if !subtract_result.is_err() {
-                return subtract_result;
+            match subtract_result {
+                ScResult::Ok(_) => return subtract_result,
+                ScResult::Err(err) => last_error = err,
             }
         }
 
-        ScResult::Err(())
+        ScResult::Err(last_error)
     }
 }
@@ -0,0 +1,189 @@
+multiversx_sc::imports!();
+multiversx_sc::derive_imports!();
+
+use crate::{service::ServiceInfo, service_profile::ServiceMetadata, subtract_payments::Epoch};
+
+pub const MAX_PAGE_SIZE: usize = 100;
+
+#[derive(TypeAbi, TopEncode, TopDecode, NestedEncode, NestedDecode)]
+pub struct ServiceView<M: ManagedTypeApi> {
+    pub service_id: AddressId,
+    pub service_address: ManagedAddress<M>,
+    pub options: ManagedVec<M, ServiceInfo<M>>,
+}
+
+#[derive(TypeAbi, TopEncode, TopDecode, NestedEncode, NestedDecode, ManagedVecItem)]
+pub struct ServiceOptionListing<M: ManagedTypeApi> {
+    pub info: ServiceInfo<M>,
+    pub label: ManagedBuffer<M>,
+    pub deprecated: bool,
+    pub subscriber_count: usize,
+}
+
+#[derive(TypeAbi, TopEncode, TopDecode, NestedEncode, NestedDecode)]
+pub struct ServiceListing<M: ManagedTypeApi> {
+    pub service_id: AddressId,
+    pub service_address: ManagedAddress<M>,
+    pub metadata: ServiceMetadata<M>,
+    pub options: ManagedVec<M, ServiceOptionListing<M>>,
+}
+
+#[derive(TypeAbi, TopEncode, TopDecode, NestedEncode, NestedDecode)]
+pub struct UserSubscriptionView {
+    pub service_id: AddressId,
+    pub service_index: usize,
+    pub next_payment_epoch: Epoch,
+}
+
+#[multiversx_sc::module]
+pub trait ViewsModule:
+    crate::common_storage::CommonStorageModule
+    + crate::events::EventsModule
+    + crate::service_profile::ServiceProfileModule
+{
+    /// Returns up to count registered services, starting from the given service ID.
+    /// IDs of unregistered services are skipped, so the next page starts after the last returned ID.
+    #[view(getServices)]
+    fn get_services(
+        &self,
+        start_service_id: AddressId,
+        count: usize,
+    ) -> MultiValueEncoded<ServiceView<Self::Api>> {
+        require!(count <= MAX_PAGE_SIZE, "Invalid page size");
+
+        let mut result = MultiValueEncoded::new();
+        let last_service_id = self.service_id().get_last_id();
+        let mut service_id = core::cmp::max(start_service_id, 1);
+        while service_id <= last_service_id && result.len() < count {
+            if let Some(service_address) = self.get_service_address(service_id) {
+                result.push(ServiceView {
+                    service_id,
+                    service_address,
+                    options: self.service_info(service_id).get(),
+                });
+            }
+
+            service_id += 1;
+        }
+
+        result
+    }
+
+    /// Same as getServices, with the metadata of each service and the label and subscriber count of each option.
+    /// If a category is provided, only the services in that category are returned.
+    #[view(getServiceListings)]
+    fn get_service_listings(
+        &self,
+        start_service_id: AddressId,
+        count: usize,
+        opt_category: OptionalValue<ManagedBuffer>,
+    ) -> MultiValueEncoded<ServiceListing<Self::Api>> {
+        require!(count <= MAX_PAGE_SIZE, "Invalid page size");
+
+        let opt_category = opt_category.into_option();
+        let mut result = MultiValueEncoded::new();
+        let last_service_id = self.service_id().get_last_id();
+        let mut service_id = core::cmp::max(start_service_id, 1);
+        while service_id <= last_service_id && result.len() < count {
+            if let Some(service_address) = self.get_service_address(service_id) {
+                let metadata = self.get_service_profile(service_id).metadata;
+                let in_category = match &opt_category {
+                    Some(category) => &metadata.category == category,
+                    None => true,
+                };
+                if in_category {
+                    result.push(ServiceListing {
+                        service_id,
+                        service_address,
+                        metadata,
+                        options: self.get_service_option_listings(service_id),
+                    });
+                }
+            }
+
+            service_id += 1;
+        }
+
+        result
+    }
+
+    fn get_service_option_listings(
+        &self,
+        service_id: AddressId,
+    ) -> ManagedVec<ServiceOptionListing<Self::Api>> {
+        let mut options = ManagedVec::new();
+        for (service_index, info) in self.service_info(service_id).get().iter().enumerate() {
+            options.push(ServiceOptionListing {
+                info,
+                label: self.service_option_label(service_id, service_index).get(),
+                deprecated: self
+                    .deprecated_service_option(service_id, service_index)
+                    .get(),
+                subscriber_count: self.subscribed_users(service_id, service_index).len(),
+            });
+        }
+
+        options
+    }
+
+    /// Returns up to count subscribers of the service option, starting from the given index
+    #[view(getServiceSubscribers)]
+    fn get_service_subscribers(
+        &self,
+        service_id: AddressId,
+        service_index: usize,
+        start_index: usize,
+        count: usize,
+    ) -> MultiValueEncoded<AddressId> {
+        require!(count <= MAX_PAGE_SIZE, "Invalid page size");
+
+        let mut result = MultiValueEncoded::new();
+        let subscribed_users_mapper = self.subscribed_users(service_id, service_index);
+        let total_users = subscribed_users_mapper.len();
+        let end_index = core::cmp::min(start_index.saturating_add(count), total_users);
+        for index in start_index..end_index {
+            result.push(subscribed_users_mapper.get_by_index(index + 1));
+        }
+
+        result
+    }
+
+    /// Returns up to count subscriptions of the user, starting from the given index, along with their next payment epoch
+    #[view(getUserSubscriptions)]
+    fn get_user_subscriptions(
+        &self,
+        user_address: ManagedAddress,
+        start_index: usize,
+        count: usize,
+    ) -> MultiValueEncoded<UserSubscriptionView> {
+        require!(count <= MAX_PAGE_SIZE, "Invalid page size");
+
+        let mut result = MultiValueEncoded::new();
+        let user_id = self.user_id().get_id(&user_address);
+        if user_id == NULL_ID {
+            return result;
+        }
+
+        let user_subscriptions_mapper = self.user_subscriptions(user_id);
+        let total_subscriptions = user_subscriptions_mapper.len();
+        let end_index = core::cmp::min(start_index.saturating_add(count), total_subscriptions);
+        for index in start_index..end_index {
+            let subscription = user_subscriptions_mapper.get_by_index(index + 1);
+            let next_payment_epoch = self
+                .user_next_payment_epoch(
+                    user_id,
+                    subscription.service_id,
+                    subscription.service_index,
+                )
+                .get();
+
+            result.push(UserSubscriptionView {
+                service_id: subscription.service_id,
+                service_index: subscription.service_index,
+                next_payment_epoch,
+            });
+        }
+
+        result
+    }
+}
@@ -141,4 +141,13 @@ where
             })
             .assert_ok();
     }
+
+    pub fn set_state(&mut self, caller: &Address, state: State) {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.pair_wrapper, &rust_biguint!(0), |sc| {
+                sc.state().set(state);
+            })
+            .assert_ok();
+    }
 }
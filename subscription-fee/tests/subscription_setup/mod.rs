@@ -3,18 +3,29 @@
 use std::{cell::RefCell, rc::Rc};
 
 use multiversx_sc::{
+    codec::multi_types::OptionalValue,
     storage::mappers::AddressId,
-    types::{Address, MultiValueEncoded},
+    types::{Address, EsdtTokenPayment, MultiValueEncoded, OperationCompletionStatus},
 };
+use multiversx_sc_modules::pause::PauseModule;
 use multiversx_sc_scenario::{
-    managed_address, managed_biguint, managed_token_id, rust_biguint,
-    testing_framework::{BlockchainStateWrapper, ContractObjWrapper, TxResult},
+    managed_address, managed_biguint, managed_buffer, managed_token_id,
+    multiversx_chain_vm::tx_mock::TxLog,
+    rust_biguint,
+    testing_framework::{BlockchainStateWrapper, ContractObjWrapper, TxResult, TxTokenTransfer},
     DebugApi,
 };
 use subscription_fee::{
+    auto_swap::AutoSwapModule,
+    common_storage::CommonStorageModule,
+    escrow::EscrowModule,
     fees::FeesModule,
+    pair_actions::{PairActionsModule, PriceSource},
+    revenue::RevenueModule,
     service::ServiceModule,
-    subtract_payments::{ScResult, SubtractPaymentsModule},
+    service_profile::ServiceProfileModule,
+    spending_limits::SpendingLimitsModule,
+    subtract_payments::{ScResult, SubtractBatchProgress, SubtractError, SubtractPaymentsModule},
     SubscriptionFee,
 };
 
@@ -22,6 +33,9 @@ use crate::{USDC_TOKEN_ID, WEGLD_TOKEN_ID};
 
 pub const MIN_USER_DEPOSIT_VALUE: u64 = 1_000_000;
 
+// opt_payment_token, payment_amount, payment_in_stable, subscription_epochs and label
+pub type ServiceOptionWithLabel<'a> = (Option<Vec<u8>>, u64, bool, u64, &'a [u8]);
+
 pub struct SubscriptionSetup<SubscriptionObjBuilder>
 where
     SubscriptionObjBuilder: 'static + Copy + Fn() -> subscription_fee::ContractObj<DebugApi>,
@@ -77,6 +91,165 @@ where
         }
     }
 
+    pub fn call_add_accepted_fees_tokens(&mut self, tokens: Vec<Vec<u8>>) -> TxResult {
+        self.b_mock.borrow_mut().execute_tx(
+            &self.owner_addr,
+            &self.s_wrapper,
+            &rust_biguint!(0),
+            |sc| {
+                let mut managed_tokens = MultiValueEncoded::new();
+                for token in tokens {
+                    managed_tokens.push(managed_token_id!(token));
+                }
+
+                sc.add_accepted_fees_tokens(managed_tokens);
+            },
+        )
+    }
+
+    pub fn call_set_token_price_route(&mut self, token_id: &[u8], route: Vec<Address>) -> TxResult {
+        self.b_mock.borrow_mut().execute_tx(
+            &self.owner_addr,
+            &self.s_wrapper,
+            &rust_biguint!(0),
+            |sc| {
+                let mut managed_route = MultiValueEncoded::new();
+                for pair_address in route {
+                    managed_route.push(managed_address!(&pair_address));
+                }
+
+                sc.set_token_price_route(managed_token_id!(token_id), managed_route);
+            },
+        )
+    }
+
+    pub fn call_set_token_fixed_rate(&mut self, token_id: &[u8], rate: u64) -> TxResult {
+        self.b_mock.borrow_mut().execute_tx(
+            &self.owner_addr,
+            &self.s_wrapper,
+            &rust_biguint!(0),
+            |sc| {
+                sc.set_token_price_source(
+                    managed_token_id!(token_id),
+                    PriceSource::FixedRate(managed_biguint!(rate)),
+                    OptionalValue::None,
+                );
+            },
+        )
+    }
+
+    pub fn call_set_token_price_aggregator_source(
+        &mut self,
+        token_id: &[u8],
+        opt_feed_names: Option<(&[u8], &[u8])>,
+    ) -> TxResult {
+        self.b_mock.borrow_mut().execute_tx(
+            &self.owner_addr,
+            &self.s_wrapper,
+            &rust_biguint!(0),
+            |sc| {
+                let opt_managed_feed_names = match opt_feed_names {
+                    Some((from, to)) => {
+                        OptionalValue::Some((managed_buffer!(from), managed_buffer!(to)).into())
+                    }
+                    None => OptionalValue::None,
+                };
+
+                sc.set_token_price_source(
+                    managed_token_id!(token_id),
+                    PriceSource::PriceAggregator,
+                    opt_managed_feed_names,
+                );
+            },
+        )
+    }
+
+    pub fn call_set_price_aggregator_address(&mut self, aggregator_address: &Address) -> TxResult {
+        self.b_mock.borrow_mut().execute_tx(
+            &self.owner_addr,
+            &self.s_wrapper,
+            &rust_biguint!(0),
+            |sc| {
+                sc.set_price_aggregator_address(managed_address!(aggregator_address));
+            },
+        )
+    }
+
+    pub fn call_set_token_decimals(&mut self, token_id: &[u8], decimals: u32) -> TxResult {
+        self.b_mock.borrow_mut().execute_tx(
+            &self.owner_addr,
+            &self.s_wrapper,
+            &rust_biguint!(0),
+            |sc| {
+                sc.set_token_decimals(managed_token_id!(token_id), decimals);
+            },
+        )
+    }
+
+    pub fn call_set_max_price_staleness(&mut self, max_price_staleness_seconds: u64) -> TxResult {
+        self.b_mock.borrow_mut().execute_tx(
+            &self.owner_addr,
+            &self.s_wrapper,
+            &rust_biguint!(0),
+            |sc| {
+                sc.set_max_price_staleness(max_price_staleness_seconds);
+            },
+        )
+    }
+
+    pub fn call_set_max_price_deviation(&mut self, max_price_deviation: u64) -> TxResult {
+        self.b_mock.borrow_mut().execute_tx(
+            &self.owner_addr,
+            &self.s_wrapper,
+            &rust_biguint!(0),
+            |sc| {
+                sc.set_max_price_deviation(max_price_deviation);
+            },
+        )
+    }
+
+    pub fn call_set_paused(&mut self, paused: bool) -> TxResult {
+        self.b_mock.borrow_mut().execute_tx(
+            &self.owner_addr,
+            &self.s_wrapper,
+            &rust_biguint!(0),
+            |sc| {
+                if paused {
+                    sc.pause_endpoint();
+                } else {
+                    sc.unpause_endpoint();
+                }
+            },
+        )
+    }
+
+    pub fn call_set_protocol_fee(
+        &mut self,
+        protocol_fee_percentage: u64,
+        claim_address: &Address,
+    ) -> TxResult {
+        self.b_mock.borrow_mut().execute_tx(
+            &self.owner_addr,
+            &self.s_wrapper,
+            &rust_biguint!(0),
+            |sc| {
+                sc.set_protocol_fee_percentage(protocol_fee_percentage);
+                sc.set_protocol_fees_claim_address(managed_address!(claim_address));
+            },
+        )
+    }
+
+    pub fn call_claim_protocol_fees(&mut self) -> TxResult {
+        self.b_mock.borrow_mut().execute_tx(
+            &self.owner_addr,
+            &self.s_wrapper,
+            &rust_biguint!(0),
+            |sc| {
+                let _ = sc.claim_protocol_fees();
+            },
+        )
+    }
+
     pub fn call_register_service(
         &mut self,
         caller: &Address,
@@ -103,6 +276,43 @@ where
             })
     }
 
+    pub fn call_register_service_with_metadata(
+        &mut self,
+        caller: &Address,
+        name: &[u8],
+        description_hash: &[u8],
+        url: &[u8],
+        category: &[u8],
+        args: Vec<ServiceOptionWithLabel>,
+    ) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                let mut args_encoded = MultiValueEncoded::new();
+                for arg in args {
+                    let (opt_token_id, value, payment_in_stable, subscription_epochs, label) = arg;
+                    args_encoded.push(
+                        (
+                            opt_token_id.map(|token_id| managed_token_id!(token_id)),
+                            managed_biguint!(value),
+                            payment_in_stable,
+                            subscription_epochs,
+                            managed_buffer!(label),
+                        )
+                            .into(),
+                    );
+                }
+
+                sc.register_service_with_metadata(
+                    managed_buffer!(name),
+                    managed_buffer!(description_hash),
+                    managed_buffer!(url),
+                    managed_buffer!(category),
+                    args_encoded,
+                );
+            })
+    }
+
     pub fn call_approve_service(&mut self, service_address: &Address) -> TxResult {
         self.b_mock.borrow_mut().execute_tx(
             &self.owner_addr,
@@ -114,6 +324,53 @@ where
         )
     }
 
+    pub fn call_update_service_option(
+        &mut self,
+        caller: &Address,
+        service_index: usize,
+        args: (Option<Vec<u8>>, u64, bool, u64),
+    ) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                let (opt_token_id, value, payment_in_stable, subscription_epochs) = args;
+                sc.update_service_option(
+                    service_index,
+                    opt_token_id.map(|token_id| managed_token_id!(token_id)),
+                    managed_biguint!(value),
+                    payment_in_stable,
+                    subscription_epochs,
+                );
+            })
+    }
+
+    pub fn call_approve_service_option_update(
+        &mut self,
+        service_address: &Address,
+        service_index: usize,
+    ) -> TxResult {
+        self.b_mock.borrow_mut().execute_tx(
+            &self.owner_addr,
+            &self.s_wrapper,
+            &rust_biguint!(0),
+            |sc| {
+                sc.approve_service_option_update(managed_address!(service_address), service_index);
+            },
+        )
+    }
+
+    pub fn call_deprecate_service_option(
+        &mut self,
+        caller: &Address,
+        service_index: usize,
+    ) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                sc.deprecate_service_option(service_index);
+            })
+    }
+
     pub fn call_unregister_service(&mut self, caller: &Address) -> TxResult {
         self.b_mock
             .borrow_mut()
@@ -122,6 +379,26 @@ where
             })
     }
 
+    pub fn call_set_grace_period(
+        &mut self,
+        caller: &Address,
+        grace_period_epochs: u64,
+    ) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                sc.set_grace_period(grace_period_epochs);
+            })
+    }
+
+    pub fn call_set_automatic_billing(&mut self, caller: &Address, enabled: bool) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                sc.set_automatic_billing(enabled);
+            })
+    }
+
     pub fn call_deposit(&mut self, caller: &Address, token_id: &[u8], amount: u64) -> TxResult {
         self.b_mock.borrow_mut().execute_esdt_transfer(
             caller,
@@ -135,61 +412,597 @@ where
         )
     }
 
-    pub fn call_subscribe(&mut self, caller: &Address, args: Vec<(AddressId, usize)>) -> TxResult {
+    pub fn call_deposit_multiple(
+        &mut self,
+        caller: &Address,
+        payments: Vec<(&[u8], u64)>,
+    ) -> TxResult {
+        let transfers: Vec<TxTokenTransfer> = payments
+            .into_iter()
+            .map(|(token_id, amount)| TxTokenTransfer {
+                token_identifier: token_id.to_vec(),
+                nonce: 0,
+                value: rust_biguint!(amount),
+            })
+            .collect();
+
+        self.b_mock.borrow_mut().execute_esdt_multi_transfer(
+            caller,
+            &self.s_wrapper,
+            &transfers,
+            |sc| {
+                sc.deposit();
+            },
+        )
+    }
+
+    pub fn call_deposit_egld(&mut self, caller: &Address, amount: u64) -> TxResult {
         self.b_mock
             .borrow_mut()
-            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
-                let mut managed_args = MultiValueEncoded::new();
-                for arg in args {
-                    managed_args.push((arg.0, arg.1).into());
-                }
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(amount), |sc| {
+                sc.deposit();
+            })
+    }
 
-                sc.subscribe(managed_args);
+    pub fn call_set_egld_wrapper_address(&mut self, wrapper_address: &Address) -> TxResult {
+        self.b_mock.borrow_mut().execute_tx(
+            &self.owner_addr,
+            &self.s_wrapper,
+            &rust_biguint!(0),
+            |sc| {
+                sc.set_egld_wrapper_address(managed_address!(wrapper_address));
+            },
+        )
+    }
+
+    pub fn call_set_prorated_refunds(
+        &mut self,
+        caller: &Address,
+        service_index: usize,
+        enabled: bool,
+    ) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                sc.set_prorated_refunds(service_index, enabled);
             })
     }
 
-    pub fn call_subtract_payment(
+    pub fn call_set_trial_period(
         &mut self,
         caller: &Address,
         service_index: usize,
-        user_id: AddressId,
+        trial_epochs: u64,
     ) -> TxResult {
         self.b_mock
             .borrow_mut()
             .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
-                let _ = sc.subtract_payment(service_index, user_id);
+                sc.set_trial_period(service_index, trial_epochs);
             })
     }
 
-    pub fn call_subtract_payment_with_sc_error(
+    pub fn call_set_energy_discount_tiers(
         &mut self,
         caller: &Address,
         service_index: usize,
-        user_id: AddressId,
+        tiers: Vec<(u64, u64)>,
     ) -> TxResult {
         self.b_mock
             .borrow_mut()
             .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
-                let result = sc.subtract_payment(service_index, user_id);
-                assert_eq!(result, ScResult::Err(()));
+                let mut managed_tiers = MultiValueEncoded::new();
+                for (min_energy, discount_percentage) in tiers {
+                    managed_tiers.push((managed_biguint!(min_energy), discount_percentage).into());
+                }
+
+                sc.set_energy_discount_tiers(service_index, managed_tiers);
             })
     }
 
-    pub fn call_withdraw_funds(
+    pub fn call_set_token_preference_order(
         &mut self,
         caller: &Address,
-        tokens: Vec<(Vec<u8>, u64)>,
+        tokens: Vec<&[u8]>,
     ) -> TxResult {
         self.b_mock
             .borrow_mut()
             .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
-                let mut managed_args = MultiValueEncoded::new();
-                for token in tokens {
-                    managed_args
-                        .push((managed_token_id!(token.0), managed_biguint!(token.1)).into());
+                let mut managed_tokens = MultiValueEncoded::new();
+                for token_id in tokens {
+                    managed_tokens.push(managed_token_id!(token_id));
                 }
 
-                let _ = sc.withdraw_funds(managed_args);
+                sc.set_token_preference_order(managed_tokens);
+            })
+    }
+
+    pub fn call_set_excluded_tokens(&mut self, caller: &Address, tokens: Vec<&[u8]>) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                let mut managed_tokens = MultiValueEncoded::new();
+                for token_id in tokens {
+                    managed_tokens.push(managed_token_id!(token_id));
+                }
+
+                sc.set_excluded_tokens(managed_tokens);
+            })
+    }
+
+    pub fn call_enable_auto_swap(&mut self, caller: &Address, max_slippage: u64) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                sc.enable_auto_swap(max_slippage);
             })
     }
+
+    pub fn call_set_withdrawal_reserve(
+        &mut self,
+        caller: &Address,
+        service_index: usize,
+        enabled: bool,
+    ) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                sc.set_withdrawal_reserve(service_index, enabled);
+            })
+    }
+
+    pub fn call_set_withdrawal_delay(&mut self, delay_epochs: u64) -> TxResult {
+        self.b_mock.borrow_mut().execute_tx(
+            &self.owner_addr,
+            &self.s_wrapper,
+            &rust_biguint!(0),
+            |sc| {
+                sc.set_withdrawal_delay(delay_epochs);
+            },
+        )
+    }
+
+    pub fn call_request_withdrawal(
+        &mut self,
+        caller: &Address,
+        tokens: Vec<(Vec<u8>, u64)>,
+    ) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                let mut managed_tokens = MultiValueEncoded::new();
+                for (token_id, amount) in tokens {
+                    managed_tokens
+                        .push((managed_token_id!(token_id), managed_biguint!(amount)).into());
+                }
+
+                sc.request_withdrawal(managed_tokens);
+            })
+    }
+
+    pub fn call_claim_withdrawal(&mut self, caller: &Address) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                let _ = sc.claim_withdrawal();
+            })
+    }
+
+    pub fn call_set_cycle_escrow(
+        &mut self,
+        caller: &Address,
+        service_index: usize,
+        enabled: bool,
+    ) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                sc.set_cycle_escrow(service_index, enabled);
+            })
+    }
+
+    pub fn check_user_balances(&mut self, user: &Address, free_amount: u64, reserved_amount: u64) {
+        self.b_mock
+            .borrow_mut()
+            .execute_query(&self.s_wrapper, |sc| {
+                let (free_balance, reserved_balance) =
+                    sc.get_user_balances(managed_address!(user)).into_tuple();
+                let free_total = free_balance
+                    .iter()
+                    .fold(managed_biguint!(0), |total, payment| total + payment.amount);
+                let reserved_total = reserved_balance
+                    .iter()
+                    .fold(managed_biguint!(0), |total, payment| total + payment.amount);
+                assert_eq!(free_total, managed_biguint!(free_amount));
+                assert_eq!(reserved_total, managed_biguint!(reserved_amount));
+            })
+            .assert_ok();
+    }
+
+    pub fn call_set_revenue_pull_mode(&mut self, caller: &Address, enabled: bool) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                sc.set_revenue_pull_mode(enabled);
+            })
+    }
+
+    pub fn call_claim_service_revenue(
+        &mut self,
+        caller: &Address,
+        destination: &Address,
+    ) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                let _ = sc.claim_service_revenue(managed_address!(destination));
+            })
+    }
+
+    pub fn call_claim_unregistered_service_revenue(
+        &mut self,
+        caller: &Address,
+        service_id: AddressId,
+        destination: &Address,
+    ) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                let _ = sc
+                    .claim_unregistered_service_revenue(service_id, managed_address!(destination));
+            })
+    }
+
+    pub fn call_set_service_payout_address(
+        &mut self,
+        caller: &Address,
+        service_id: AddressId,
+        payout_address: &Address,
+    ) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                sc.set_service_payout_address(service_id, managed_address!(payout_address));
+            })
+    }
+
+    pub fn call_transfer_service_ownership(
+        &mut self,
+        caller: &Address,
+        service_id: AddressId,
+        new_owner: &Address,
+    ) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                sc.transfer_service_ownership(service_id, managed_address!(new_owner));
+            })
+    }
+
+    pub fn call_accept_service_ownership(
+        &mut self,
+        caller: &Address,
+        service_id: AddressId,
+    ) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                sc.accept_service_ownership(service_id);
+            })
+    }
+
+    pub fn call_set_service_option_label(
+        &mut self,
+        caller: &Address,
+        service_id: AddressId,
+        service_index: usize,
+        label: &[u8],
+    ) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                sc.set_service_option_label(service_id, service_index, managed_buffer!(label));
+            })
+    }
+
+    pub fn call_change_service_address(
+        &mut self,
+        caller: &Address,
+        service_id: AddressId,
+        new_address: &Address,
+    ) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                sc.change_service_address(service_id, managed_address!(new_address));
+            })
+    }
+
+    pub fn call_set_spending_cap(
+        &mut self,
+        caller: &Address,
+        service_id: AddressId,
+        token_id: &[u8],
+        max_amount: u64,
+        period_epochs: u64,
+    ) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                sc.set_spending_cap(
+                    service_id,
+                    managed_token_id!(token_id),
+                    managed_biguint!(max_amount),
+                    period_epochs,
+                );
+            })
+    }
+
+    pub fn call_set_service_token_whitelist(
+        &mut self,
+        caller: &Address,
+        service_id: AddressId,
+        tokens: Vec<&[u8]>,
+    ) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                let mut managed_tokens = MultiValueEncoded::new();
+                for token_id in tokens {
+                    managed_tokens.push(managed_token_id!(token_id));
+                }
+
+                sc.set_service_token_whitelist(service_id, managed_tokens);
+            })
+    }
+
+    pub fn call_pay_refunds(
+        &mut self,
+        caller: &Address,
+        token_id: &[u8],
+        amount: u64,
+        user_ids: Vec<AddressId>,
+    ) -> TxResult {
+        self.b_mock.borrow_mut().execute_esdt_transfer(
+            caller,
+            &self.s_wrapper,
+            token_id,
+            0,
+            &rust_biguint!(amount),
+            |sc| {
+                let mut managed_user_ids = MultiValueEncoded::new();
+                for user_id in user_ids {
+                    managed_user_ids.push(user_id);
+                }
+
+                sc.pay_refunds(managed_user_ids);
+            },
+        )
+    }
+
+    pub fn call_backfill_user_subscriptions(
+        &mut self,
+        expected_status: OperationCompletionStatus,
+    ) -> TxResult {
+        self.b_mock.borrow_mut().execute_tx(
+            &self.owner_addr,
+            &self.s_wrapper,
+            &rust_biguint!(0),
+            |sc| {
+                let status = sc.backfill_user_subscriptions();
+                assert_eq!(status, expected_status);
+            },
+        )
+    }
+
+    pub fn call_subscribe(&mut self, caller: &Address, args: Vec<(AddressId, usize)>) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                let mut managed_args = MultiValueEncoded::new();
+                for arg in args {
+                    managed_args.push((arg.0, arg.1).into());
+                }
+
+                sc.subscribe(managed_args);
+            })
+    }
+
+    pub fn call_subscribe_and_deposit(
+        &mut self,
+        caller: &Address,
+        token_id: &[u8],
+        amount: u64,
+        args: Vec<(AddressId, usize)>,
+    ) -> TxResult {
+        self.b_mock.borrow_mut().execute_esdt_transfer(
+            caller,
+            &self.s_wrapper,
+            token_id,
+            0,
+            &rust_biguint!(amount),
+            |sc| {
+                let mut managed_args = MultiValueEncoded::new();
+                for arg in args {
+                    managed_args.push((arg.0, arg.1).into());
+                }
+
+                let _ = sc.subscribe_and_deposit(managed_args);
+            },
+        )
+    }
+
+    pub fn call_unsubscribe(
+        &mut self,
+        caller: &Address,
+        args: Vec<(AddressId, usize)>,
+    ) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                let mut managed_args = MultiValueEncoded::new();
+                for arg in args {
+                    managed_args.push((arg.0, arg.1).into());
+                }
+
+                sc.unsubscribe(managed_args);
+            })
+    }
+
+    pub fn call_unsubscribe_all(&mut self, caller: &Address) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                let _ = sc.unsubscribe_all();
+            })
+    }
+
+    pub fn call_subtract_payment(
+        &mut self,
+        caller: &Address,
+        service_index: usize,
+        user_id: AddressId,
+    ) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                let _ = sc.subtract_payment(service_index, user_id);
+            })
+    }
+
+    pub fn call_subtract_payment_with_sc_error(
+        &mut self,
+        caller: &Address,
+        service_index: usize,
+        user_id: AddressId,
+        expected_error: SubtractError,
+    ) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                let result = sc.subtract_payment(service_index, user_id);
+                assert_eq!(result, ScResult::Err(expected_error));
+            })
+    }
+
+    pub fn call_subtract_payment_batch(
+        &mut self,
+        caller: &Address,
+        args: Vec<(usize, AddressId)>,
+        expected_start_index: usize,
+        expected_results: Vec<ScResult<(Vec<u8>, u64), SubtractError>>,
+    ) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                let mut managed_args = MultiValueEncoded::new();
+                for arg in args {
+                    managed_args.push((arg.0, arg.1).into());
+                }
+
+                let (status, start_index, results) =
+                    sc.subtract_payment_batch(managed_args).into_tuple();
+                assert_eq!(status, OperationCompletionStatus::Completed);
+                assert_eq!(start_index, expected_start_index);
+
+                let results: Vec<_> = results.into_iter().collect();
+                assert_eq!(results.len(), expected_results.len());
+                for (result, expected_result) in results.into_iter().zip(expected_results.iter()) {
+                    let expected_result = match expected_result {
+                        ScResult::Ok((token_id, amount)) => ScResult::Ok(EsdtTokenPayment::new(
+                            managed_token_id!(token_id.clone()),
+                            0,
+                            managed_biguint!(*amount),
+                        )),
+                        ScResult::Err(err) => ScResult::Err(*err),
+                    };
+                    assert_eq!(result, expected_result);
+                }
+            })
+    }
+
+    /// Simulates a batch of the service that was interrupted after processing the given number of arguments
+    pub fn set_subtract_batch_progress(
+        &mut self,
+        service_id: AddressId,
+        args: Vec<(usize, AddressId)>,
+        processed: usize,
+    ) {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(&self.owner_addr, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                let mut managed_args = MultiValueEncoded::new();
+                for arg in args {
+                    managed_args.push((arg.0, arg.1).into());
+                }
+
+                let args_hash = sc.get_batch_args_hash(&managed_args);
+                sc.subtract_batch_progress(service_id)
+                    .set(SubtractBatchProgress {
+                        args_hash,
+                        processed,
+                    });
+            })
+            .assert_ok();
+    }
+
+    pub fn call_process_due_subscriptions(
+        &mut self,
+        caller: &Address,
+        service_id: AddressId,
+        service_index: usize,
+        page_size: usize,
+    ) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                let _ = sc.process_due_subscriptions(service_id, service_index, page_size);
+            })
+    }
+
+    pub fn call_withdraw_funds(
+        &mut self,
+        caller: &Address,
+        tokens: Vec<(Vec<u8>, u64)>,
+    ) -> TxResult {
+        self.b_mock
+            .borrow_mut()
+            .execute_tx(caller, &self.s_wrapper, &rust_biguint!(0), |sc| {
+                let mut managed_args = MultiValueEncoded::new();
+                for token in tokens {
+                    managed_args
+                        .push((managed_token_id!(token.0), managed_biguint!(token.1)).into());
+                }
+
+                let _ = sc.withdraw_funds(managed_args);
+            })
+    }
+}
+
+/// Logs of the given event emitted during the transaction
+pub fn get_event_logs(tx_result: &TxResult, event_identifier: &[u8]) -> Vec<TxLog> {
+    tx_result
+        .result_logs
+        .iter()
+        .filter(|log| log.topics[0] == event_identifier)
+        .cloned()
+        .collect()
+}
+
+/// Encoding of a fungible payment, as found in the event data
+pub fn encode_payment(token_id: &[u8], amount: u64) -> Vec<u8> {
+    let amount_bytes: Vec<u8> = amount
+        .to_be_bytes()
+        .iter()
+        .copied()
+        .skip_while(|byte| *byte == 0)
+        .collect();
+
+    let mut encoded = Vec::new();
+    encoded.extend_from_slice(&(token_id.len() as u32).to_be_bytes());
+    encoded.extend_from_slice(token_id);
+    encoded.extend_from_slice(&0u64.to_be_bytes());
+    encoded.extend_from_slice(&(amount_bytes.len() as u32).to_be_bytes());
+    encoded.extend_from_slice(&amount_bytes);
+
+    encoded
 }
@@ -2,13 +2,29 @@
 
 use std::{cell::RefCell, rc::Rc};
 
+use egld_wrapper_mock::EgldWrapperMock;
+use energy_factory::energy::EnergyModule;
+use energy_query::{Energy, EnergyQueryModule};
+use multiversx_sc::{
+    codec::multi_types::OptionalValue,
+    types::{Address, BigInt, EsdtLocalRole, OperationCompletionStatus},
+};
 use multiversx_sc_scenario::{
-    managed_address, managed_token_id, rust_biguint, testing_framework::BlockchainStateWrapper,
-    DebugApi,
+    managed_address, managed_biguint, managed_buffer, managed_token_id, rust_biguint,
+    testing_framework::BlockchainStateWrapper, DebugApi,
 };
 use pair_setup::PairSetup;
-use subscription_fee::pair_actions::PairActionsModule;
-use subscription_setup::SubscriptionSetup;
+use pausable::State;
+use price_aggregator_mock::PriceAggregatorMock;
+use subscription_fee::{
+    common_storage::CommonStorageModule,
+    pair_actions::{PairActionsModule, PRICE_PRECISION},
+    service::UserSubscription,
+    service_profile::ServiceProfileModule,
+    subtract_payments::{ScResult, SubtractError, SubtractPaymentsModule},
+    views::ViewsModule,
+};
+use subscription_setup::{encode_payment, get_event_logs, SubscriptionSetup};
 
 mod pair_setup;
 mod subscription_setup;
@@ -17,6 +33,7 @@ static FIRST_TOKEN_ID: &[u8] = b"MYTOKEN-123456";
 static USDC_TOKEN_ID: &[u8] = b"USDC-123456";
 static WEGLD_TOKEN_ID: &[u8] = b"WEGLD-123456";
 static LP_TOKEN_ID: &[u8] = b"LPTOK-123456";
+static OTHER_TOKEN_ID: &[u8] = b"OTHER-123456";
 
 pub const DAILY_SUBSCRIPTION_EPOCHS: u64 = 1;
 
@@ -291,6 +308,88 @@ fn subtract_ok_test() {
         .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(1_000));
 }
 
+#[test]
+fn events_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![(
+                Some(FIRST_TOKEN_ID.to_vec()),
+                1_000,
+                false,
+                DAILY_SUBSCRIPTION_EPOCHS,
+            )],
+        )
+        .assert_ok();
+
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+
+    let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(1_000_000));
+
+    b_mock_rc.borrow_mut().set_block_epoch(5);
+
+    let tx_result = sub_sc.call_deposit(&user, FIRST_TOKEN_ID, 1_000_000);
+    tx_result.assert_ok();
+
+    let deposit_logs = get_event_logs(&tx_result, b"depositEvent");
+    assert_eq!(deposit_logs.len(), 1);
+    assert_eq!(
+        deposit_logs[0].topics,
+        vec![b"depositEvent".to_vec(), vec![1], vec![5]]
+    );
+    assert_eq!(
+        deposit_logs[0].data,
+        vec![encode_payment(FIRST_TOKEN_ID, 1_000_000)]
+    );
+
+    let tx_result = sub_sc.call_subscribe(&user, vec![(1, 0)]);
+    tx_result.assert_ok();
+
+    // the service index is 0, which top-encodes to an empty topic
+    let subscribe_logs = get_event_logs(&tx_result, b"subscribeEvent");
+    assert_eq!(subscribe_logs.len(), 1);
+    assert_eq!(
+        subscribe_logs[0].topics,
+        vec![
+            b"subscribeEvent".to_vec(),
+            vec![1],
+            vec![1],
+            Vec::new(),
+            vec![5]
+        ]
+    );
+
+    b_mock_rc.borrow_mut().set_block_epoch(10);
+
+    let tx_result = sub_sc.call_subtract_payment(&rand_service, 0, 1);
+    tx_result.assert_ok();
+
+    let charge_logs = get_event_logs(&tx_result, b"subtractPaymentEvent");
+    assert_eq!(charge_logs.len(), 1);
+    assert_eq!(
+        charge_logs[0].topics,
+        vec![
+            b"subtractPaymentEvent".to_vec(),
+            vec![1],
+            vec![1],
+            Vec::new(),
+            vec![10]
+        ]
+    );
+    assert_eq!(
+        charge_logs[0].data,
+        vec![encode_payment(FIRST_TOKEN_ID, 1_000)]
+    );
+}
+
 #[test]
 fn try_subtract_twice_same_day() {
     let (b_mock_rc, _pair_setup, mut sub_sc) =
@@ -334,8 +433,8 @@ fn try_subtract_twice_same_day() {
         .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(1_000));
 
     sub_sc
-        .call_subtract_payment(&rand_service, 0, 1)
-        .assert_user_error("Cannot subtract yet");
+        .call_subtract_payment_with_sc_error(&rand_service, 0, 1, SubtractError::NotDueYet)
+        .assert_ok();
 
     // still same balance
     b_mock_rc
@@ -478,9 +577,9 @@ fn try_substract_from_unsubscribed_user_test() {
 
     b_mock_rc.borrow_mut().set_block_epoch(10);
 
-    // The endpoint returns a ScResult::Err(()), but does not stop the execution
+    // The endpoint returns a ScResult::Err, but does not stop the execution
     sub_sc
-        .call_subtract_payment_with_sc_error(&rand_service, 0, 1)
+        .call_subtract_payment_with_sc_error(&rand_service, 0, 1, SubtractError::NotSubscribed)
         .assert_ok();
 
     // The service was not able to substract the payment for the wrong service index
@@ -497,3 +596,2964 @@ fn try_substract_from_unsubscribed_user_test() {
         .borrow()
         .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(500));
 }
+
+#[test]
+fn subtract_payment_batch_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![
+                (
+                    Some(FIRST_TOKEN_ID.to_vec()),
+                    1_000,
+                    false,
+                    DAILY_SUBSCRIPTION_EPOCHS,
+                ),
+                (
+                    Some(FIRST_TOKEN_ID.to_vec()),
+                    500,
+                    false,
+                    DAILY_SUBSCRIPTION_EPOCHS,
+                ),
+            ],
+        )
+        .assert_ok();
+
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+
+    let first_user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    let second_user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    for user in [&first_user, &second_user] {
+        b_mock_rc
+            .borrow_mut()
+            .set_esdt_balance(user, FIRST_TOKEN_ID, &rust_biguint!(1_000_000));
+
+        sub_sc
+            .call_deposit(user, FIRST_TOKEN_ID, 1_000_000)
+            .assert_ok();
+    }
+
+    sub_sc.call_subscribe(&first_user, vec![(1, 0)]).assert_ok();
+    sub_sc
+        .call_subscribe(&second_user, vec![(1, 1)])
+        .assert_ok();
+
+    b_mock_rc.borrow_mut().set_block_epoch(10);
+
+    // second user is not subscribed to the first option, so that entry fails without stopping the batch
+    sub_sc
+        .call_subtract_payment_batch(
+            &rand_service,
+            vec![(0, 1), (0, 2), (1, 2)],
+            0,
+            vec![
+                ScResult::Ok((FIRST_TOKEN_ID.to_vec(), 1_000)),
+                ScResult::Err(SubtractError::NotSubscribed),
+                ScResult::Ok((FIRST_TOKEN_ID.to_vec(), 500)),
+            ],
+        )
+        .assert_ok();
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(1_500));
+
+    // users were already charged for the current epoch
+    sub_sc
+        .call_subtract_payment_batch(
+            &rand_service,
+            vec![(0, 1), (1, 2)],
+            0,
+            vec![
+                ScResult::Err(SubtractError::NotDueYet),
+                ScResult::Err(SubtractError::NotDueYet),
+            ],
+        )
+        .assert_ok();
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(1_500));
+
+    // an interrupted batch only resumes for the exact same arguments
+    b_mock_rc
+        .borrow_mut()
+        .set_block_epoch(10 + DAILY_SUBSCRIPTION_EPOCHS);
+    sub_sc.set_subtract_batch_progress(1, vec![(0, 1), (1, 2)], 1);
+    sub_sc
+        .call_subtract_payment_batch(
+            &rand_service,
+            vec![(1, 2), (0, 1)],
+            0,
+            vec![
+                ScResult::Ok((FIRST_TOKEN_ID.to_vec(), 500)),
+                ScResult::Ok((FIRST_TOKEN_ID.to_vec(), 1_000)),
+            ],
+        )
+        .assert_ok();
+
+    b_mock_rc
+        .borrow_mut()
+        .set_block_epoch(10 + 2 * DAILY_SUBSCRIPTION_EPOCHS);
+    sub_sc.set_subtract_batch_progress(1, vec![(0, 1), (1, 2)], 1);
+    sub_sc
+        .call_subtract_payment_batch(
+            &rand_service,
+            vec![(0, 1), (1, 2)],
+            1,
+            vec![ScResult::Ok((FIRST_TOKEN_ID.to_vec(), 500))],
+        )
+        .assert_ok();
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(3_500));
+    b_mock_rc
+        .borrow_mut()
+        .execute_query(&sub_sc.s_wrapper, |sc| {
+            assert!(sc.subtract_batch_progress(1).is_empty());
+        })
+        .assert_ok();
+}
+
+#[test]
+fn process_due_subscriptions_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![(
+                Some(FIRST_TOKEN_ID.to_vec()),
+                1_000,
+                false,
+                DAILY_SUBSCRIPTION_EPOCHS,
+            )],
+        )
+        .assert_ok();
+
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+
+    let first_user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    let second_user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    for user in [&first_user, &second_user] {
+        b_mock_rc
+            .borrow_mut()
+            .set_esdt_balance(user, FIRST_TOKEN_ID, &rust_biguint!(1_000_000));
+
+        sub_sc
+            .call_deposit(user, FIRST_TOKEN_ID, 1_000_000)
+            .assert_ok();
+        sub_sc.call_subscribe(user, vec![(1, 0)]).assert_ok();
+    }
+
+    b_mock_rc.borrow_mut().set_block_epoch(10);
+
+    // the service has to opt in first
+    let rand_caller = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_process_due_subscriptions(&rand_caller, 1, 0, 1)
+        .assert_user_error("Automatic billing not enabled");
+    sub_sc
+        .call_set_automatic_billing(&rand_service, true)
+        .assert_ok();
+
+    // anyone can trigger the billing, one user per page
+    sub_sc
+        .call_process_due_subscriptions(&rand_caller, 1, 0, 1)
+        .assert_ok();
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(1_000));
+
+    sub_sc
+        .call_process_due_subscriptions(&rand_caller, 1, 0, 1)
+        .assert_ok();
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(2_000));
+
+    // nobody is due anymore in the current epoch
+    sub_sc
+        .call_process_due_subscriptions(&rand_caller, 1, 0, 10)
+        .assert_ok();
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(2_000));
+
+    b_mock_rc
+        .borrow_mut()
+        .set_block_epoch(10 + DAILY_SUBSCRIPTION_EPOCHS);
+
+    sub_sc
+        .call_process_due_subscriptions(&rand_caller, 1, 0, 10)
+        .assert_ok();
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(4_000));
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_caller, FIRST_TOKEN_ID, &rust_zero);
+
+    sub_sc
+        .call_process_due_subscriptions(&rand_caller, 1, 0, 0)
+        .assert_user_error("Invalid page size");
+}
+
+#[test]
+fn grace_period_auto_unsubscribe_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![(
+                Some(FIRST_TOKEN_ID.to_vec()),
+                1_000,
+                false,
+                DAILY_SUBSCRIPTION_EPOCHS,
+            )],
+        )
+        .assert_ok();
+
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+    sub_sc.call_set_grace_period(&rand_service, 2).assert_ok();
+
+    let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(1_000_000));
+
+    sub_sc
+        .call_deposit(&user, FIRST_TOKEN_ID, 1_000_000)
+        .assert_ok();
+    sub_sc.call_subscribe(&user, vec![(1, 0)]).assert_ok();
+    sub_sc
+        .call_withdraw_funds(&user, vec![(FIRST_TOKEN_ID.to_vec(), 999_999)])
+        .assert_ok();
+
+    // first failed charge puts the user in the past due state
+    b_mock_rc.borrow_mut().set_block_epoch(10);
+    sub_sc
+        .call_subtract_payment_with_sc_error(
+            &rand_service,
+            0,
+            1,
+            SubtractError::InsufficientBalance,
+        )
+        .assert_ok();
+
+    b_mock_rc
+        .borrow_mut()
+        .execute_query(&sub_sc.s_wrapper, |sc| {
+            assert_eq!(sc.failed_charge_attempts(1, 1, 0).get(), 1);
+            assert_eq!(sc.past_due_epoch(1, 1, 0).get(), 10);
+            assert!(sc.subscribed_users(1, 0).contains(&1));
+        })
+        .assert_ok();
+
+    // still inside the grace period
+    b_mock_rc.borrow_mut().set_block_epoch(11);
+    sub_sc
+        .call_subtract_payment_with_sc_error(
+            &rand_service,
+            0,
+            1,
+            SubtractError::InsufficientBalance,
+        )
+        .assert_ok();
+
+    b_mock_rc
+        .borrow_mut()
+        .execute_query(&sub_sc.s_wrapper, |sc| {
+            assert_eq!(sc.failed_charge_attempts(1, 1, 0).get(), 2);
+            assert!(sc.subscribed_users(1, 0).contains(&1));
+        })
+        .assert_ok();
+
+    // grace period is over, the user is removed from the service
+    b_mock_rc.borrow_mut().set_block_epoch(12);
+    sub_sc
+        .call_subtract_payment_with_sc_error(
+            &rand_service,
+            0,
+            1,
+            SubtractError::InsufficientBalance,
+        )
+        .assert_ok();
+
+    b_mock_rc
+        .borrow_mut()
+        .execute_query(&sub_sc.s_wrapper, |sc| {
+            assert!(sc.failed_charge_attempts(1, 1, 0).is_empty());
+            assert!(sc.past_due_epoch(1, 1, 0).is_empty());
+            assert!(!sc.subscribed_users(1, 0).contains(&1));
+        })
+        .assert_ok();
+}
+
+#[test]
+fn process_due_subscriptions_with_auto_unsubscribe_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![(
+                Some(FIRST_TOKEN_ID.to_vec()),
+                1_000,
+                false,
+                DAILY_SUBSCRIPTION_EPOCHS,
+            )],
+        )
+        .assert_ok();
+
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+    sub_sc.call_set_grace_period(&rand_service, 1).assert_ok();
+    sub_sc
+        .call_set_automatic_billing(&rand_service, true)
+        .assert_ok();
+
+    // the first user cannot pay, the other two can
+    let mut users = Vec::new();
+    for _ in 0..3 {
+        let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+        b_mock_rc
+            .borrow_mut()
+            .set_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(1_000_000));
+        sub_sc
+            .call_deposit(&user, FIRST_TOKEN_ID, 1_000_000)
+            .assert_ok();
+        sub_sc.call_subscribe(&user, vec![(1, 0)]).assert_ok();
+        users.push(user);
+    }
+
+    sub_sc
+        .call_withdraw_funds(&users[0], vec![(FIRST_TOKEN_ID.to_vec(), 999_999)])
+        .assert_ok();
+
+    let rand_caller = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc.borrow_mut().set_block_epoch(10);
+    sub_sc
+        .call_process_due_subscriptions(&rand_caller, 1, 0, 3)
+        .assert_ok();
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(2_000));
+
+    // the grace period is over, so the first user is removed in the middle of the page,
+    // and the last subscriber is moved into their place and still charged
+    b_mock_rc.borrow_mut().set_block_epoch(11);
+    sub_sc
+        .call_process_due_subscriptions(&rand_caller, 1, 0, 3)
+        .assert_ok();
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(4_000));
+
+    b_mock_rc
+        .borrow_mut()
+        .execute_query(&sub_sc.s_wrapper, |sc| {
+            assert_eq!(sc.subscribed_users(1, 0).len(), 2);
+            assert!(!sc.subscribed_users(1, 0).contains(&1));
+            assert!(sc.due_subscriptions_cursor(1, 0).is_empty());
+        })
+        .assert_ok();
+}
+
+#[test]
+fn price_query_failure_not_past_due_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![(
+                Some(FIRST_TOKEN_ID.to_vec()),
+                1_000,
+                true,
+                DAILY_SUBSCRIPTION_EPOCHS,
+            )],
+        )
+        .assert_ok();
+
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+    sub_sc.call_set_grace_period(&rand_service, 1).assert_ok();
+
+    let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(1_000_000));
+    sub_sc
+        .call_deposit(&user, FIRST_TOKEN_ID, 1_000_000)
+        .assert_ok();
+    sub_sc.call_subscribe(&user, vec![(1, 0)]).assert_ok();
+
+    b_mock_rc
+        .borrow_mut()
+        .execute_tx(&sub_sc.owner_addr, &sub_sc.s_wrapper, &rust_zero, |sc| {
+            sc.remove_pair_address(managed_token_id!(FIRST_TOKEN_ID));
+        })
+        .assert_ok();
+
+    // the user is not held responsible for a missing price, even after the grace period
+    for epoch in [10, 11, 12] {
+        b_mock_rc.borrow_mut().set_block_epoch(epoch);
+        sub_sc
+            .call_subtract_payment_with_sc_error(
+                &rand_service,
+                0,
+                1,
+                SubtractError::PriceQueryFailed,
+            )
+            .assert_ok();
+    }
+
+    b_mock_rc
+        .borrow_mut()
+        .execute_query(&sub_sc.s_wrapper, |sc| {
+            assert_eq!(sc.failed_charge_attempts(1, 1, 0).get(), 0);
+            assert!(sc.past_due_epoch(1, 1, 0).is_empty());
+            assert!(sc.subscribed_users(1, 0).contains(&1));
+        })
+        .assert_ok();
+}
+
+#[test]
+fn past_due_user_recovers_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![(
+                Some(FIRST_TOKEN_ID.to_vec()),
+                1_000,
+                false,
+                DAILY_SUBSCRIPTION_EPOCHS,
+            )],
+        )
+        .assert_ok();
+
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+    sub_sc.call_set_grace_period(&rand_service, 2).assert_ok();
+
+    let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(1_000_000));
+
+    sub_sc
+        .call_deposit(&user, FIRST_TOKEN_ID, 1_000_000)
+        .assert_ok();
+    sub_sc.call_subscribe(&user, vec![(1, 0)]).assert_ok();
+    sub_sc
+        .call_withdraw_funds(&user, vec![(FIRST_TOKEN_ID.to_vec(), 999_999)])
+        .assert_ok();
+
+    b_mock_rc.borrow_mut().set_block_epoch(10);
+    sub_sc
+        .call_subtract_payment_with_sc_error(
+            &rand_service,
+            0,
+            1,
+            SubtractError::InsufficientBalance,
+        )
+        .assert_ok();
+
+    // user tops up during the grace period
+    sub_sc
+        .call_deposit(&user, FIRST_TOKEN_ID, 999_999)
+        .assert_ok();
+
+    b_mock_rc.borrow_mut().set_block_epoch(11);
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_ok();
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(1_000));
+    b_mock_rc
+        .borrow_mut()
+        .execute_query(&sub_sc.s_wrapper, |sc| {
+            assert!(sc.failed_charge_attempts(1, 1, 0).is_empty());
+            assert!(sc.past_due_epoch(1, 1, 0).is_empty());
+            assert!(sc.subscribed_users(1, 0).contains(&1));
+        })
+        .assert_ok();
+}
+
+#[test]
+fn multi_hop_price_route_test() {
+    let (b_mock_rc, payment_pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    // OTHER token only trades against the payment token
+    let owner = sub_sc.owner_addr.clone();
+    let other_pair_setup = PairSetup::new(
+        b_mock_rc.clone(),
+        pair::contract_obj,
+        &owner,
+        OTHER_TOKEN_ID,
+        FIRST_TOKEN_ID,
+        LP_TOKEN_ID,
+        1_000_000_000,
+        2_000_000_000,
+    );
+
+    let mut stable_pair_address = Address::zero();
+    b_mock_rc
+        .borrow_mut()
+        .execute_query(&sub_sc.s_wrapper, |sc| {
+            stable_pair_address = sc
+                .pair_address_for_token(&managed_token_id!(WEGLD_TOKEN_ID))
+                .get()
+                .to_address();
+        })
+        .assert_ok();
+
+    sub_sc
+        .call_add_accepted_fees_tokens(vec![OTHER_TOKEN_ID.to_vec()])
+        .assert_ok();
+
+    // the route must start from the stable token and end in the given token
+    sub_sc
+        .call_set_token_price_route(
+            OTHER_TOKEN_ID,
+            vec![
+                payment_pair_setup.pair_wrapper.address_ref().clone(),
+                other_pair_setup.pair_wrapper.address_ref().clone(),
+            ],
+        )
+        .assert_user_error("Invalid route");
+    sub_sc
+        .call_set_token_price_route(
+            OTHER_TOKEN_ID,
+            vec![
+                stable_pair_address.clone(),
+                payment_pair_setup.pair_wrapper.address_ref().clone(),
+            ],
+        )
+        .assert_user_error("Route does not end in token");
+    sub_sc
+        .call_set_token_price_route(
+            OTHER_TOKEN_ID,
+            vec![
+                stable_pair_address,
+                payment_pair_setup.pair_wrapper.address_ref().clone(),
+                other_pair_setup.pair_wrapper.address_ref().clone(),
+            ],
+        )
+        .assert_ok();
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![(
+                Some(OTHER_TOKEN_ID.to_vec()),
+                1_000,
+                true,
+                DAILY_SUBSCRIPTION_EPOCHS,
+            )],
+        )
+        .assert_ok();
+
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+
+    let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, OTHER_TOKEN_ID, &rust_biguint!(1_000_000));
+
+    sub_sc
+        .call_deposit(&user, OTHER_TOKEN_ID, 1_000_000)
+        .assert_ok();
+    sub_sc.call_subscribe(&user, vec![(1, 0)]).assert_ok();
+
+    b_mock_rc.borrow_mut().set_block_epoch(10);
+
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_ok();
+
+    // 1000 USDC -> 500 WEGLD -> 500 payment tokens -> 250 OTHER tokens
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, OTHER_TOKEN_ID, &rust_biguint!(250));
+}
+
+#[test]
+fn fixed_rate_price_source_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    sub_sc
+        .call_add_accepted_fees_tokens(vec![OTHER_TOKEN_ID.to_vec()])
+        .assert_ok();
+
+    sub_sc
+        .call_set_token_price_aggregator_source(OTHER_TOKEN_ID, None)
+        .assert_user_error("Price aggregator address not set");
+    sub_sc
+        .call_set_token_fixed_rate(OTHER_TOKEN_ID, 0)
+        .assert_user_error("Invalid rate");
+
+    // 3 OTHER tokens for each stable token
+    sub_sc
+        .call_set_token_fixed_rate(OTHER_TOKEN_ID, 3 * PRICE_PRECISION)
+        .assert_ok();
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![(
+                Some(OTHER_TOKEN_ID.to_vec()),
+                1_000,
+                true,
+                DAILY_SUBSCRIPTION_EPOCHS,
+            )],
+        )
+        .assert_ok();
+
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+
+    let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, OTHER_TOKEN_ID, &rust_biguint!(3_000_000));
+
+    sub_sc
+        .call_deposit(&user, OTHER_TOKEN_ID, 3_000_000)
+        .assert_ok();
+    sub_sc.call_subscribe(&user, vec![(1, 0)]).assert_ok();
+
+    b_mock_rc.borrow_mut().set_block_epoch(10);
+
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_ok();
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, OTHER_TOKEN_ID, &rust_biguint!(3_000));
+}
+
+#[test]
+fn price_aggregator_source_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    let owner = sub_sc.owner_addr.clone();
+    let aggregator = b_mock_rc.borrow_mut().create_sc_account(
+        &rust_zero,
+        Some(&owner),
+        price_aggregator_mock::contract_obj,
+        "price aggregator",
+    );
+    b_mock_rc
+        .borrow_mut()
+        .execute_tx(&owner, &aggregator, &rust_zero, |sc| {
+            sc.init();
+        })
+        .assert_ok();
+
+    sub_sc
+        .call_add_accepted_fees_tokens(vec![OTHER_TOKEN_ID.to_vec()])
+        .assert_ok();
+    sub_sc
+        .call_set_price_aggregator_address(aggregator.address_ref())
+        .assert_ok();
+    sub_sc
+        .call_set_token_price_aggregator_source(OTHER_TOKEN_ID, Some((b"OTHER", b"USD")))
+        .assert_user_error("Token decimals not set");
+
+    sub_sc
+        .call_set_token_decimals(OTHER_TOKEN_ID, 6)
+        .assert_ok();
+    sub_sc.call_set_token_decimals(USDC_TOKEN_ID, 6).assert_ok();
+    sub_sc
+        .call_set_token_price_aggregator_source(OTHER_TOKEN_ID, Some((b"OTHER", b"")))
+        .assert_user_error("Invalid feed names");
+
+    // the USDC-123456 ticker does not match the USD feed, so the feed names are set explicitly
+    sub_sc
+        .call_set_token_price_aggregator_source(OTHER_TOKEN_ID, Some((b"OTHER", b"USD")))
+        .assert_ok();
+    sub_sc.call_set_max_price_staleness(100).assert_ok();
+
+    // 2 USD for each OTHER token
+    b_mock_rc.borrow_mut().set_block_timestamp(1_000);
+    b_mock_rc
+        .borrow_mut()
+        .execute_tx(&owner, &aggregator, &rust_zero, |sc| {
+            sc.set_latest_price_feed(
+                managed_buffer!(b"OTHER"),
+                managed_buffer!(b"USD"),
+                managed_biguint!(2_000_000),
+                6,
+                1_000,
+            );
+        })
+        .assert_ok();
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![(
+                Some(OTHER_TOKEN_ID.to_vec()),
+                1_000,
+                true,
+                DAILY_SUBSCRIPTION_EPOCHS,
+            )],
+        )
+        .assert_ok();
+
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+
+    let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, OTHER_TOKEN_ID, &rust_biguint!(1_000_000));
+    sub_sc
+        .call_deposit(&user, OTHER_TOKEN_ID, 1_000_000)
+        .assert_ok();
+    sub_sc.call_subscribe(&user, vec![(1, 0)]).assert_ok();
+
+    b_mock_rc.borrow_mut().set_block_epoch(10);
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_ok();
+
+    // 1000 USDC at 2 USD per token -> 500 OTHER tokens
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, OTHER_TOKEN_ID, &rust_biguint!(500));
+
+    // the feed was not updated within the max staleness
+    b_mock_rc.borrow_mut().set_block_timestamp(1_101);
+    b_mock_rc.borrow_mut().set_block_epoch(11);
+    sub_sc
+        .call_subtract_payment_with_sc_error(&rand_service, 0, 1, SubtractError::PriceQueryFailed)
+        .assert_ok();
+
+    b_mock_rc
+        .borrow_mut()
+        .execute_tx(&owner, &aggregator, &rust_zero, |sc| {
+            sc.set_latest_price_feed(
+                managed_buffer!(b"OTHER"),
+                managed_buffer!(b"USD"),
+                managed_biguint!(4_000_000),
+                6,
+                1_100,
+            );
+        })
+        .assert_ok();
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_ok();
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, OTHER_TOKEN_ID, &rust_biguint!(750));
+}
+
+#[test]
+fn price_aggregator_deviation_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    let owner = sub_sc.owner_addr.clone();
+    let aggregator = b_mock_rc.borrow_mut().create_sc_account(
+        &rust_zero,
+        Some(&owner),
+        price_aggregator_mock::contract_obj,
+        "price aggregator",
+    );
+    b_mock_rc
+        .borrow_mut()
+        .execute_tx(&owner, &aggregator, &rust_zero, |sc| {
+            sc.init();
+        })
+        .assert_ok();
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![(
+                Some(FIRST_TOKEN_ID.to_vec()),
+                1_000,
+                true,
+                DAILY_SUBSCRIPTION_EPOCHS,
+            )],
+        )
+        .assert_ok();
+
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+
+    let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(1_000_000));
+    sub_sc
+        .call_deposit(&user, FIRST_TOKEN_ID, 1_000_000)
+        .assert_ok();
+    sub_sc.call_subscribe(&user, vec![(1, 0)]).assert_ok();
+
+    // the DEX price is cross-checked with the aggregator feed, named after the token tickers by default
+    sub_sc
+        .call_set_max_price_deviation(1_000)
+        .assert_user_error("Price aggregator address not set");
+    sub_sc
+        .call_set_price_aggregator_address(aggregator.address_ref())
+        .assert_ok();
+    sub_sc
+        .call_set_max_price_deviation(1_000)
+        .assert_user_error("Token decimals not set");
+    sub_sc
+        .call_set_token_decimals(FIRST_TOKEN_ID, 6)
+        .assert_ok();
+    sub_sc.call_set_token_decimals(USDC_TOKEN_ID, 6).assert_ok();
+    sub_sc.call_set_max_price_deviation(1_000).assert_ok();
+
+    // without a reference price, the DEX price is not used
+    b_mock_rc.borrow_mut().set_block_epoch(5);
+    sub_sc
+        .call_subtract_payment_with_sc_error(&rand_service, 0, 1, SubtractError::PriceQueryFailed)
+        .assert_ok();
+
+    // the DEX values 1000 USDC at 500 tokens, while the aggregator values them at 250
+    b_mock_rc
+        .borrow_mut()
+        .execute_tx(&owner, &aggregator, &rust_zero, |sc| {
+            sc.set_latest_price_feed(
+                managed_buffer!(b"MYTOKEN"),
+                managed_buffer!(b"USDC"),
+                managed_biguint!(4_000_000),
+                6,
+                0,
+            );
+        })
+        .assert_ok();
+
+    b_mock_rc.borrow_mut().set_block_epoch(10);
+    sub_sc
+        .call_subtract_payment_with_sc_error(&rand_service, 0, 1, SubtractError::PriceQueryFailed)
+        .assert_ok();
+
+    // within 10% of the DEX price
+    b_mock_rc
+        .borrow_mut()
+        .execute_tx(&owner, &aggregator, &rust_zero, |sc| {
+            sc.set_latest_price_feed(
+                managed_buffer!(b"MYTOKEN"),
+                managed_buffer!(b"USDC"),
+                managed_biguint!(2_100_000),
+                6,
+                0,
+            );
+        })
+        .assert_ok();
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_ok();
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(500));
+
+    // a stale reference price is not used either
+    sub_sc.call_set_max_price_staleness(100).assert_ok();
+    b_mock_rc.borrow_mut().set_block_timestamp(1_000);
+    b_mock_rc.borrow_mut().set_block_epoch(11);
+    sub_sc
+        .call_subtract_payment_with_sc_error(&rand_service, 0, 1, SubtractError::PriceQueryFailed)
+        .assert_ok();
+}
+
+#[test]
+fn subscribe_and_deposit_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![(
+                Some(FIRST_TOKEN_ID.to_vec()),
+                1_000,
+                false,
+                DAILY_SUBSCRIPTION_EPOCHS,
+            )],
+        )
+        .assert_ok();
+
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+
+    let first_user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    let second_user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    for user in [&first_user, &second_user].iter().copied() {
+        b_mock_rc
+            .borrow_mut()
+            .set_esdt_balance(user, FIRST_TOKEN_ID, &rust_biguint!(2_000_000));
+    }
+
+    b_mock_rc.borrow_mut().set_block_epoch(10);
+
+    // without automatic billing, the service charges the first cycle itself
+    sub_sc
+        .call_subscribe_and_deposit(&first_user, FIRST_TOKEN_ID, 1_000_000, vec![(1, 0)])
+        .assert_ok();
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_zero);
+
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_ok();
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(1_000));
+
+    sub_sc
+        .call_subscribe_and_deposit(&first_user, FIRST_TOKEN_ID, 1_000_000, vec![(1, 0)])
+        .assert_user_error("Already subscribed");
+
+    // with automatic billing, the first cycle is charged immediately
+    sub_sc
+        .call_set_automatic_billing(&rand_service, true)
+        .assert_ok();
+    sub_sc
+        .call_subscribe_and_deposit(&second_user, FIRST_TOKEN_ID, 1_000_000, vec![(1, 0)])
+        .assert_ok();
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(2_000));
+
+    sub_sc
+        .call_subtract_payment_with_sc_error(&rand_service, 0, 2, SubtractError::NotDueYet)
+        .assert_ok();
+
+    b_mock_rc
+        .borrow_mut()
+        .set_block_epoch(10 + DAILY_SUBSCRIPTION_EPOCHS);
+
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 2)
+        .assert_ok();
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(3_000));
+}
+
+#[test]
+fn unsubscribe_all_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![
+                (
+                    Some(FIRST_TOKEN_ID.to_vec()),
+                    1_000,
+                    false,
+                    DAILY_SUBSCRIPTION_EPOCHS,
+                ),
+                (
+                    Some(FIRST_TOKEN_ID.to_vec()),
+                    5_000,
+                    false,
+                    DAILY_SUBSCRIPTION_EPOCHS,
+                ),
+            ],
+        )
+        .assert_ok();
+
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+
+    let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(1_000_000));
+
+    sub_sc
+        .call_deposit(&user, FIRST_TOKEN_ID, 1_000_000)
+        .assert_ok();
+    sub_sc
+        .call_subscribe(&user, vec![(1, 0), (1, 1)])
+        .assert_ok();
+
+    b_mock_rc.borrow_mut().set_block_epoch(10);
+
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_ok();
+
+    sub_sc.call_unsubscribe_all(&user).assert_ok();
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(999_000));
+
+    b_mock_rc
+        .borrow_mut()
+        .execute_query(&sub_sc.s_wrapper, |sc| {
+            assert!(sc.user_subscriptions(1).is_empty());
+            assert!(sc.subscribed_users(1, 0).is_empty());
+            assert!(sc.subscribed_users(1, 1).is_empty());
+            assert!(sc.user_deposited_fees(1).is_empty());
+        })
+        .assert_ok();
+
+    sub_sc
+        .call_subtract_payment_with_sc_error(&rand_service, 1, 1, SubtractError::NotSubscribed)
+        .assert_ok();
+
+    // a token fully spent on charges is not sent back
+    let second_user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc.borrow_mut().set_esdt_balance(
+        &second_user,
+        FIRST_TOKEN_ID,
+        &rust_biguint!(1_000_000),
+    );
+    sub_sc
+        .call_deposit(&second_user, FIRST_TOKEN_ID, 1_000_000)
+        .assert_ok();
+    sub_sc
+        .call_subscribe(&second_user, vec![(1, 0)])
+        .assert_ok();
+    sub_sc
+        .call_withdraw_funds(&second_user, vec![(FIRST_TOKEN_ID.to_vec(), 999_000)])
+        .assert_ok();
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 2)
+        .assert_ok();
+
+    sub_sc.call_unsubscribe_all(&second_user).assert_ok();
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&second_user, FIRST_TOKEN_ID, &rust_biguint!(999_000));
+    b_mock_rc
+        .borrow_mut()
+        .execute_query(&sub_sc.s_wrapper, |sc| {
+            assert!(sc.user_deposited_fees(2).is_empty());
+        })
+        .assert_ok();
+}
+
+#[test]
+fn backfill_user_subscriptions_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    // nothing to backfill yet
+    sub_sc
+        .call_backfill_user_subscriptions(OperationCompletionStatus::Completed)
+        .assert_ok();
+
+    let mut services = Vec::new();
+    for _ in 0..2 {
+        let service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+        sub_sc
+            .call_register_service(
+                &service,
+                vec![
+                    (
+                        Some(FIRST_TOKEN_ID.to_vec()),
+                        1_000,
+                        false,
+                        DAILY_SUBSCRIPTION_EPOCHS,
+                    ),
+                    (Some(FIRST_TOKEN_ID.to_vec()), 5_000, false, 30),
+                ],
+            )
+            .assert_ok();
+        sub_sc.call_approve_service(&service).assert_ok();
+        services.push(service);
+    }
+
+    let mut users = Vec::new();
+    for subscriptions in [
+        vec![(1, 0), (2, 1)],
+        vec![(1, 1)],
+        vec![(1, 0), (1, 1), (2, 0)],
+    ] {
+        let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+        b_mock_rc
+            .borrow_mut()
+            .set_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(1_000_000));
+        sub_sc
+            .call_deposit(&user, FIRST_TOKEN_ID, 1_000_000)
+            .assert_ok();
+        sub_sc.call_subscribe(&user, subscriptions).assert_ok();
+        users.push(user);
+    }
+
+    // subscriptions made before the index existed are only in the subscribers of each option
+    b_mock_rc
+        .borrow_mut()
+        .execute_tx(&sub_sc.owner_addr, &sub_sc.s_wrapper, &rust_zero, |sc| {
+            for user_id in 1..=3 {
+                let mut user_subscriptions_mapper = sc.user_subscriptions(user_id);
+                let user_subscriptions: Vec<UserSubscription> =
+                    user_subscriptions_mapper.iter().collect();
+                for subscription in user_subscriptions.iter() {
+                    let _ = user_subscriptions_mapper.swap_remove(subscription);
+                }
+            }
+        })
+        .assert_ok();
+
+    // the second service is gone, so its subscribers are not indexed
+    sub_sc.call_unregister_service(&services[1]).assert_ok();
+
+    sub_sc
+        .call_backfill_user_subscriptions(OperationCompletionStatus::Completed)
+        .assert_ok();
+
+    b_mock_rc
+        .borrow_mut()
+        .execute_query(&sub_sc.s_wrapper, |sc| {
+            for (user_id, expected_indexes) in [(1, vec![0]), (2, vec![1]), (3, vec![0, 1])] {
+                let user_subscriptions = sc.user_subscriptions(user_id);
+                assert_eq!(user_subscriptions.len(), expected_indexes.len());
+                for service_index in expected_indexes {
+                    assert!(user_subscriptions.contains(&UserSubscription {
+                        service_id: 1,
+                        service_index,
+                    }));
+                }
+            }
+
+            assert!(sc.user_subscriptions_backfill_progress().is_empty());
+        })
+        .assert_ok();
+
+    // backfilled subscriptions are removed by unsubscribeAll
+    sub_sc.call_unsubscribe_all(&users[2]).assert_ok();
+
+    b_mock_rc
+        .borrow_mut()
+        .execute_query(&sub_sc.s_wrapper, |sc| {
+            assert!(!sc.subscribed_users(1, 0).contains(&3));
+            assert!(!sc.subscribed_users(1, 1).contains(&3));
+        })
+        .assert_ok();
+}
+
+#[test]
+fn prorated_refund_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![(Some(FIRST_TOKEN_ID.to_vec()), 3_000, false, 30)],
+        )
+        .assert_ok();
+
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+    sub_sc
+        .call_set_prorated_refunds(&rand_service, 1, true)
+        .assert_user_error("Invalid service index");
+    sub_sc
+        .call_set_prorated_refunds(&rand_service, 0, true)
+        .assert_ok();
+
+    let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(1_000_000));
+
+    sub_sc
+        .call_deposit(&user, FIRST_TOKEN_ID, 1_000_000)
+        .assert_ok();
+    sub_sc.call_subscribe(&user, vec![(1, 0)]).assert_ok();
+
+    b_mock_rc.borrow_mut().set_block_epoch(10);
+
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_ok();
+
+    // 20 of the 30 paid epochs are left
+    b_mock_rc.borrow_mut().set_block_epoch(20);
+    sub_sc.call_unsubscribe(&user, vec![(1, 0)]).assert_ok();
+
+    b_mock_rc
+        .borrow_mut()
+        .execute_query(&sub_sc.s_wrapper, |sc| {
+            let pending_refunds = sc.pending_refunds(1, 1).get().into_payments();
+            assert_eq!(pending_refunds.len(), 1);
+            assert_eq!(pending_refunds.get(0).amount, managed_biguint!(2_000));
+        })
+        .assert_ok();
+
+    sub_sc
+        .call_pay_refunds(&rand_service, FIRST_TOKEN_ID, 1_000, vec![1])
+        .assert_user_error("Insufficient refund payment");
+    let tx_result = sub_sc.call_pay_refunds(&rand_service, FIRST_TOKEN_ID, 2_500, vec![1]);
+    tx_result.assert_ok();
+
+    // the refund is emitted for the user and the service
+    let refund_logs = get_event_logs(&tx_result, b"refundEvent");
+    assert_eq!(refund_logs.len(), 1);
+    assert_eq!(
+        refund_logs[0].topics,
+        vec![b"refundEvent".to_vec(), vec![1], vec![1], vec![20]]
+    );
+    assert_eq!(
+        refund_logs[0].data,
+        vec![encode_payment(FIRST_TOKEN_ID, 2_000)]
+    );
+
+    // the extra tokens are sent back to the service
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(1_000));
+
+    b_mock_rc
+        .borrow_mut()
+        .execute_query(&sub_sc.s_wrapper, |sc| {
+            assert!(sc.pending_refunds(1, 1).is_empty());
+            assert!(sc.users_with_pending_refunds(1).is_empty());
+
+            let user_deposits = sc.user_deposited_fees(1).get().into_payments();
+            assert_eq!(user_deposits.get(0).amount, managed_biguint!(999_000));
+        })
+        .assert_ok();
+}
+
+#[test]
+fn free_trial_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![(
+                Some(FIRST_TOKEN_ID.to_vec()),
+                1_000,
+                false,
+                DAILY_SUBSCRIPTION_EPOCHS,
+            )],
+        )
+        .assert_ok();
+
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+    sub_sc
+        .call_set_trial_period(&rand_service, 0, 5)
+        .assert_ok();
+
+    let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(1_000_000));
+
+    b_mock_rc.borrow_mut().set_block_epoch(10);
+
+    // first cycle is free, so nothing is charged until the trial ends
+    sub_sc
+        .call_subscribe_and_deposit(&user, FIRST_TOKEN_ID, 1_000_000, vec![(1, 0)])
+        .assert_ok();
+
+    b_mock_rc.borrow_mut().set_block_epoch(14);
+    sub_sc
+        .call_subtract_payment_with_sc_error(&rand_service, 0, 1, SubtractError::NotDueYet)
+        .assert_ok();
+
+    b_mock_rc.borrow_mut().set_block_epoch(15);
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_ok();
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(1_000));
+
+    // the trial cannot be used again by subscribing again
+    sub_sc.call_unsubscribe(&user, vec![(1, 0)]).assert_ok();
+    sub_sc.call_subscribe(&user, vec![(1, 0)]).assert_ok();
+
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_ok();
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(2_000));
+}
+
+#[test]
+fn update_and_deprecate_service_option_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![(
+                Some(FIRST_TOKEN_ID.to_vec()),
+                1_000,
+                false,
+                DAILY_SUBSCRIPTION_EPOCHS,
+            )],
+        )
+        .assert_ok();
+
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+
+    let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(1_000_000));
+
+    sub_sc
+        .call_deposit(&user, FIRST_TOKEN_ID, 1_000_000)
+        .assert_ok();
+    sub_sc.call_subscribe(&user, vec![(1, 0)]).assert_ok();
+
+    b_mock_rc.borrow_mut().set_block_epoch(10);
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_ok();
+
+    sub_sc
+        .call_update_service_option(
+            &rand_service,
+            0,
+            (
+                Some(FIRST_TOKEN_ID.to_vec()),
+                2_000,
+                false,
+                DAILY_SUBSCRIPTION_EPOCHS,
+            ),
+        )
+        .assert_ok();
+
+    // the old price is used until the owner approves the update
+    b_mock_rc.borrow_mut().set_block_epoch(11);
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_ok();
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(2_000));
+
+    sub_sc
+        .call_approve_service_option_update(&rand_service, 0)
+        .assert_ok();
+    sub_sc
+        .call_approve_service_option_update(&rand_service, 0)
+        .assert_user_error("No pending update");
+
+    b_mock_rc.borrow_mut().set_block_epoch(12);
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_ok();
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(4_000));
+
+    b_mock_rc
+        .borrow_mut()
+        .execute_query(&sub_sc.s_wrapper, |sc| {
+            assert_eq!(sc.service_option_version(1, 0).get(), 1);
+            assert_eq!(sc.user_option_version(1, 1, 0).get(), 1);
+        })
+        .assert_ok();
+
+    // deprecated options keep their subscribers, but do not accept new ones
+    sub_sc
+        .call_deprecate_service_option(&rand_service, 0)
+        .assert_ok();
+    sub_sc
+        .call_update_service_option(
+            &rand_service,
+            0,
+            (
+                Some(FIRST_TOKEN_ID.to_vec()),
+                3_000,
+                false,
+                DAILY_SUBSCRIPTION_EPOCHS,
+            ),
+        )
+        .assert_user_error("Service option deprecated");
+
+    let second_user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc.borrow_mut().set_esdt_balance(
+        &second_user,
+        FIRST_TOKEN_ID,
+        &rust_biguint!(1_000_000),
+    );
+    sub_sc
+        .call_deposit(&second_user, FIRST_TOKEN_ID, 1_000_000)
+        .assert_ok();
+    sub_sc
+        .call_subscribe(&second_user, vec![(1, 0)])
+        .assert_user_error("Service option deprecated");
+
+    b_mock_rc.borrow_mut().set_block_epoch(13);
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_ok();
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(6_000));
+}
+
+#[test]
+fn paginated_views_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    let first_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    let second_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    for service in [&first_service, &second_service] {
+        sub_sc
+            .call_register_service(
+                service,
+                vec![
+                    (
+                        Some(FIRST_TOKEN_ID.to_vec()),
+                        1_000,
+                        false,
+                        DAILY_SUBSCRIPTION_EPOCHS,
+                    ),
+                    (None, 1_000, true, DAILY_SUBSCRIPTION_EPOCHS),
+                ],
+            )
+            .assert_ok();
+        sub_sc.call_approve_service(service).assert_ok();
+    }
+
+    let mut users = Vec::new();
+    for _ in 0..3 {
+        let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+        b_mock_rc
+            .borrow_mut()
+            .set_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(1_000_000));
+        sub_sc
+            .call_deposit(&user, FIRST_TOKEN_ID, 1_000_000)
+            .assert_ok();
+        sub_sc
+            .call_subscribe(&user, vec![(1, 0), (2, 1)])
+            .assert_ok();
+        users.push(user);
+    }
+
+    b_mock_rc.borrow_mut().set_block_epoch(10);
+    sub_sc
+        .call_subtract_payment(&first_service, 0, 2)
+        .assert_ok();
+
+    b_mock_rc
+        .borrow_mut()
+        .execute_query(&sub_sc.s_wrapper, |sc| {
+            let services: Vec<_> = sc.get_services(2, 10).into_iter().collect();
+            assert_eq!(services.len(), 1);
+            assert_eq!(services[0].service_id, 2);
+            assert_eq!(
+                services[0].service_address,
+                managed_address!(&second_service)
+            );
+            assert_eq!(services[0].options.len(), 2);
+
+            let subscribers: Vec<_> = sc.get_service_subscribers(1, 0, 1, 5).into_iter().collect();
+            assert_eq!(subscribers, vec![2, 3]);
+
+            let subscriptions: Vec<_> = sc
+                .get_user_subscriptions(managed_address!(&users[1]), 0, 5)
+                .into_iter()
+                .collect();
+            assert_eq!(subscriptions.len(), 2);
+            assert_eq!(subscriptions[0].service_id, 1);
+            assert_eq!(
+                subscriptions[0].next_payment_epoch,
+                10 + DAILY_SUBSCRIPTION_EPOCHS
+            );
+            assert_eq!(subscriptions[1].service_id, 2);
+            assert_eq!(subscriptions[1].service_index, 1);
+            assert_eq!(subscriptions[1].next_payment_epoch, 0);
+        })
+        .assert_ok();
+}
+
+#[test]
+fn pause_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![(
+                Some(FIRST_TOKEN_ID.to_vec()),
+                1_000,
+                false,
+                DAILY_SUBSCRIPTION_EPOCHS,
+            )],
+        )
+        .assert_ok();
+
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+
+    let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(2_000_000));
+
+    sub_sc
+        .call_deposit(&user, FIRST_TOKEN_ID, 1_000_000)
+        .assert_ok();
+    sub_sc.call_subscribe(&user, vec![(1, 0)]).assert_ok();
+
+    sub_sc.call_set_paused(true).assert_ok();
+
+    b_mock_rc.borrow_mut().set_block_epoch(10);
+
+    sub_sc
+        .call_deposit(&user, FIRST_TOKEN_ID, 1_000_000)
+        .assert_user_error("Contract is paused");
+    sub_sc
+        .call_subscribe(&user, vec![(1, 0)])
+        .assert_user_error("Contract is paused");
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_user_error("Contract is paused");
+
+    // users can still withdraw their funds
+    sub_sc
+        .call_withdraw_funds(&user, vec![(FIRST_TOKEN_ID.to_vec(), 500_000)])
+        .assert_ok();
+
+    sub_sc.call_set_paused(false).assert_ok();
+
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_ok();
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(1_000));
+}
+
+#[test]
+fn protocol_fee_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    let treasury = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_set_protocol_fee(10_000, &treasury)
+        .assert_user_error("Invalid protocol fee percentage");
+
+    // 10% of each charge goes to the protocol
+    sub_sc.call_set_protocol_fee(1_000, &treasury).assert_ok();
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![(
+                Some(FIRST_TOKEN_ID.to_vec()),
+                1_000,
+                false,
+                DAILY_SUBSCRIPTION_EPOCHS,
+            )],
+        )
+        .assert_ok();
+
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+
+    let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(1_000_000));
+
+    sub_sc
+        .call_deposit(&user, FIRST_TOKEN_ID, 1_000_000)
+        .assert_ok();
+    sub_sc.call_subscribe(&user, vec![(1, 0)]).assert_ok();
+
+    b_mock_rc.borrow_mut().set_block_epoch(10);
+
+    let tx_result =
+        b_mock_rc
+            .borrow_mut()
+            .execute_tx(&rand_service, &sub_sc.s_wrapper, &rust_zero, |sc| {
+                let result = sc.subtract_payment(0, 1);
+                match result {
+                    ScResult::Ok(payment) => assert_eq!(payment.amount, managed_biguint!(900)),
+                    ScResult::Err(_) => panic!("Expected successful charge"),
+                }
+            });
+    tx_result.assert_ok();
+
+    // the charge event carries the net amount, and the fee is emitted separately
+    let charge_logs = get_event_logs(&tx_result, b"subtractPaymentEvent");
+    assert_eq!(charge_logs.len(), 1);
+    assert_eq!(
+        charge_logs[0].data,
+        vec![encode_payment(FIRST_TOKEN_ID, 900)]
+    );
+
+    let fee_logs = get_event_logs(&tx_result, b"protocolFeeEvent");
+    assert_eq!(fee_logs.len(), 1);
+    assert_eq!(fee_logs[0].data, vec![encode_payment(FIRST_TOKEN_ID, 100)]);
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(900));
+
+    sub_sc.call_claim_protocol_fees().assert_ok();
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&treasury, FIRST_TOKEN_ID, &rust_biguint!(100));
+
+    b_mock_rc
+        .borrow_mut()
+        .execute_query(&sub_sc.s_wrapper, |sc| {
+            assert!(sc.protocol_fees().is_empty());
+        })
+        .assert_ok();
+}
+
+#[test]
+fn energy_discount_tiers_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    let owner = sub_sc.owner_addr.clone();
+    let energy_factory_wrapper = b_mock_rc.borrow_mut().create_sc_account(
+        &rust_zero,
+        Some(&owner),
+        energy_factory::contract_obj,
+        "energy factory",
+    );
+    b_mock_rc
+        .borrow_mut()
+        .execute_tx(&owner, &sub_sc.s_wrapper, &rust_zero, |sc| {
+            sc.set_energy_factory_address(managed_address!(energy_factory_wrapper.address_ref()));
+        })
+        .assert_ok();
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![(
+                Some(FIRST_TOKEN_ID.to_vec()),
+                1_000,
+                false,
+                DAILY_SUBSCRIPTION_EPOCHS,
+            )],
+        )
+        .assert_ok();
+
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+    sub_sc
+        .call_set_energy_discount_tiers(&rand_service, 0, vec![(1_000, 2_000), (500, 5_000)])
+        .assert_user_error("Tiers must be in increasing order of energy");
+    sub_sc
+        .call_set_energy_discount_tiers(&rand_service, 0, vec![(1_000, 10_000)])
+        .assert_user_error("Invalid discount percentage");
+
+    // 20% discount from 1,000 energy, 50% discount from 5,000 energy
+    sub_sc
+        .call_set_energy_discount_tiers(&rand_service, 0, vec![(1_000, 2_000), (5_000, 5_000)])
+        .assert_ok();
+
+    for energy in [0u64, 1_000, 10_000] {
+        let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+        b_mock_rc
+            .borrow_mut()
+            .set_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(1_000_000));
+        sub_sc
+            .call_deposit(&user, FIRST_TOKEN_ID, 1_000_000)
+            .assert_ok();
+        sub_sc.call_subscribe(&user, vec![(1, 0)]).assert_ok();
+
+        b_mock_rc
+            .borrow_mut()
+            .execute_tx(&user, &energy_factory_wrapper, &rust_zero, |sc| {
+                sc.user_energy(&managed_address!(&user)).set(Energy::new(
+                    BigInt::from(managed_biguint!(energy)),
+                    0,
+                    managed_biguint!(0),
+                ));
+            })
+            .assert_ok();
+    }
+
+    b_mock_rc.borrow_mut().set_block_epoch(10);
+
+    let mut expected_service_balance = 0;
+    for (user_id, expected_amount) in [(1, 1_000u64), (2, 800), (3, 500)] {
+        sub_sc
+            .call_subtract_payment(&rand_service, 0, user_id)
+            .assert_ok();
+
+        expected_service_balance += expected_amount;
+        b_mock_rc.borrow().check_esdt_balance(
+            &rand_service,
+            FIRST_TOKEN_ID,
+            &rust_biguint!(expected_service_balance),
+        );
+    }
+
+    // a discount can round the charge down to zero, in which case nothing is sent to the service
+    sub_sc
+        .call_set_energy_discount_tiers(&rand_service, 0, vec![(5_000, 9_999)])
+        .assert_ok();
+    b_mock_rc.borrow_mut().set_block_epoch(11);
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 3)
+        .assert_ok();
+
+    b_mock_rc.borrow().check_esdt_balance(
+        &rand_service,
+        FIRST_TOKEN_ID,
+        &rust_biguint!(expected_service_balance),
+    );
+}
+
+#[test]
+fn spending_cap_and_token_whitelist_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![(
+                Some(FIRST_TOKEN_ID.to_vec()),
+                1_000,
+                false,
+                DAILY_SUBSCRIPTION_EPOCHS,
+            )],
+        )
+        .assert_ok();
+
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+
+    let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(1_000_000));
+    sub_sc
+        .call_deposit(&user, FIRST_TOKEN_ID, 1_000_000)
+        .assert_ok();
+    sub_sc.call_subscribe(&user, vec![(1, 0)]).assert_ok();
+
+    sub_sc
+        .call_set_spending_cap(&user, 2, FIRST_TOKEN_ID, 1_500, 30)
+        .assert_user_error("Unknown service");
+
+    // at most 1,500 tokens every 30 epochs
+    sub_sc
+        .call_set_spending_cap(&user, 1, FIRST_TOKEN_ID, 1_500, 30)
+        .assert_ok();
+
+    b_mock_rc.borrow_mut().set_block_epoch(10);
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_ok();
+
+    b_mock_rc.borrow_mut().set_block_epoch(11);
+    sub_sc
+        .call_subtract_payment_with_sc_error(
+            &rand_service,
+            0,
+            1,
+            SubtractError::SpendingCapExceeded,
+        )
+        .assert_ok();
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(1_000));
+
+    // new period
+    b_mock_rc.borrow_mut().set_block_epoch(40);
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_ok();
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(2_000));
+
+    sub_sc
+        .call_set_service_token_whitelist(&user, 1, vec![OTHER_TOKEN_ID])
+        .assert_ok();
+
+    b_mock_rc.borrow_mut().set_block_epoch(80);
+    sub_sc
+        .call_subtract_payment_with_sc_error(&rand_service, 0, 1, SubtractError::TokenNotAllowed)
+        .assert_ok();
+
+    // clearing the whitelist allows any token again
+    sub_sc
+        .call_set_service_token_whitelist(&user, 1, Vec::new())
+        .assert_ok();
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_ok();
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(3_000));
+}
+
+#[test]
+fn token_preference_order_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    sub_sc
+        .call_add_accepted_fees_tokens(vec![OTHER_TOKEN_ID.to_vec()])
+        .assert_ok();
+    sub_sc
+        .call_set_token_fixed_rate(OTHER_TOKEN_ID, 3 * PRICE_PRECISION)
+        .assert_ok();
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![(None, 1_000, false, DAILY_SUBSCRIPTION_EPOCHS)],
+        )
+        .assert_ok();
+
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+
+    let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(1_000_000));
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, OTHER_TOKEN_ID, &rust_biguint!(3_000_000));
+    sub_sc
+        .call_deposit(&user, FIRST_TOKEN_ID, 1_000_000)
+        .assert_ok();
+    sub_sc
+        .call_deposit(&user, OTHER_TOKEN_ID, 3_000_000)
+        .assert_ok();
+    sub_sc.call_subscribe(&user, vec![(1, 0)]).assert_ok();
+
+    sub_sc
+        .call_set_token_preference_order(&user, vec![OTHER_TOKEN_ID, OTHER_TOKEN_ID])
+        .assert_user_error("Duplicate token");
+    sub_sc
+        .call_set_token_preference_order(&user, vec![OTHER_TOKEN_ID])
+        .assert_ok();
+
+    b_mock_rc.borrow_mut().set_block_epoch(10);
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_ok();
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, OTHER_TOKEN_ID, &rust_biguint!(3_000));
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_zero);
+
+    // excluded tokens are skipped, even if preferred
+    sub_sc
+        .call_set_excluded_tokens(&user, vec![OTHER_TOKEN_ID])
+        .assert_ok();
+
+    b_mock_rc.borrow_mut().set_block_epoch(11);
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_ok();
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, OTHER_TOKEN_ID, &rust_biguint!(3_000));
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(500));
+
+    sub_sc
+        .call_set_excluded_tokens(&user, vec![FIRST_TOKEN_ID, OTHER_TOKEN_ID])
+        .assert_ok();
+
+    b_mock_rc.borrow_mut().set_block_epoch(12);
+    sub_sc
+        .call_subtract_payment_with_sc_error(&rand_service, 0, 1, SubtractError::TokenNotAllowed)
+        .assert_ok();
+}
+
+#[test]
+fn multi_token_deposit_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    sub_sc
+        .call_add_accepted_fees_tokens(vec![OTHER_TOKEN_ID.to_vec()])
+        .assert_ok();
+    sub_sc
+        .call_set_token_fixed_rate(OTHER_TOKEN_ID, 3 * PRICE_PRECISION)
+        .assert_ok();
+
+    let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(2_000_000));
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, OTHER_TOKEN_ID, &rust_biguint!(3_000_000));
+
+    // every transfer must be worth the minimum deposit
+    sub_sc
+        .call_deposit_multiple(
+            &user,
+            vec![(FIRST_TOKEN_ID, 1_000_000), (OTHER_TOKEN_ID, 100)],
+        )
+        .assert_user_error("Payment value is lesser than the minimum accepted");
+
+    sub_sc
+        .call_deposit_multiple(
+            &user,
+            vec![
+                (FIRST_TOKEN_ID, 1_000_000),
+                (OTHER_TOKEN_ID, 3_000_000),
+                (FIRST_TOKEN_ID, 1_000_000),
+            ],
+        )
+        .assert_ok();
+
+    b_mock_rc
+        .borrow_mut()
+        .execute_query(&sub_sc.s_wrapper, |sc| {
+            let user_deposits = sc.user_deposited_fees(1).get().into_payments();
+            assert_eq!(user_deposits.len(), 2);
+            assert_eq!(
+                user_deposits.get(0).token_identifier,
+                managed_token_id!(FIRST_TOKEN_ID)
+            );
+            assert_eq!(user_deposits.get(0).amount, managed_biguint!(2_000_000));
+            assert_eq!(
+                user_deposits.get(1).token_identifier,
+                managed_token_id!(OTHER_TOKEN_ID)
+            );
+            assert_eq!(user_deposits.get(1).amount, managed_biguint!(3_000_000));
+        })
+        .assert_ok();
+}
+
+#[test]
+fn egld_deposit_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    let owner = sub_sc.owner_addr.clone();
+    let wrapper = b_mock_rc.borrow_mut().create_sc_account(
+        &rust_zero,
+        Some(&owner),
+        egld_wrapper_mock::contract_obj,
+        "egld wrapper",
+    );
+    b_mock_rc
+        .borrow_mut()
+        .execute_tx(&owner, &wrapper, &rust_zero, |sc| {
+            sc.init(managed_token_id!(WEGLD_TOKEN_ID));
+        })
+        .assert_ok();
+    b_mock_rc.borrow_mut().set_esdt_local_roles(
+        wrapper.address_ref(),
+        WEGLD_TOKEN_ID,
+        &[EsdtLocalRole::Mint],
+    );
+
+    sub_sc
+        .call_add_accepted_fees_tokens(vec![WEGLD_TOKEN_ID.to_vec()])
+        .assert_ok();
+
+    let user = b_mock_rc
+        .borrow_mut()
+        .create_user_account(&rust_biguint!(2_000_000));
+    sub_sc
+        .call_deposit_egld(&user, 1_000_000)
+        .assert_user_error("EGLD wrapper address not set");
+
+    sub_sc
+        .call_set_egld_wrapper_address(wrapper.address_ref())
+        .assert_ok();
+    sub_sc.call_deposit_egld(&user, 1_000_000).assert_ok();
+
+    b_mock_rc
+        .borrow()
+        .check_egld_balance(&user, &rust_biguint!(1_000_000));
+    b_mock_rc
+        .borrow()
+        .check_egld_balance(wrapper.address_ref(), &rust_biguint!(1_000_000));
+    b_mock_rc.borrow().check_esdt_balance(
+        sub_sc.s_wrapper.address_ref(),
+        WEGLD_TOKEN_ID,
+        &rust_biguint!(1_000_000),
+    );
+
+    b_mock_rc
+        .borrow_mut()
+        .execute_query(&sub_sc.s_wrapper, |sc| {
+            let user_deposits = sc.user_deposited_fees(1).get().into_payments();
+            assert_eq!(user_deposits.len(), 1);
+            assert_eq!(
+                user_deposits.get(0).token_identifier,
+                managed_token_id!(WEGLD_TOKEN_ID)
+            );
+            assert_eq!(user_deposits.get(0).amount, managed_biguint!(1_000_000));
+        })
+        .assert_ok();
+}
+
+#[test]
+fn auto_swap_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    sub_sc
+        .call_add_accepted_fees_tokens(vec![WEGLD_TOKEN_ID.to_vec()])
+        .assert_ok();
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![(
+                Some(WEGLD_TOKEN_ID.to_vec()),
+                1_000,
+                false,
+                DAILY_SUBSCRIPTION_EPOCHS,
+            )],
+        )
+        .assert_ok();
+
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+
+    let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(1_000_000));
+    sub_sc
+        .call_deposit(&user, FIRST_TOKEN_ID, 1_000_000)
+        .assert_ok();
+    sub_sc.call_subscribe(&user, vec![(1, 0)]).assert_ok();
+
+    b_mock_rc.borrow_mut().set_block_epoch(10);
+    sub_sc
+        .call_subtract_payment_with_sc_error(&rand_service, 0, 1, SubtractError::TokenNotDeposited)
+        .assert_ok();
+
+    sub_sc
+        .call_enable_auto_swap(&user, 10_001)
+        .assert_user_error("Invalid slippage");
+
+    // 1% slippage
+    sub_sc.call_enable_auto_swap(&user, 100).assert_ok();
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_ok();
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, WEGLD_TOKEN_ID, &rust_biguint!(1_000));
+
+    // the pair has a 1:1 ratio, so 1,010 tokens were swapped and the surplus is kept in the user's deposits
+    b_mock_rc
+        .borrow_mut()
+        .execute_query(&sub_sc.s_wrapper, |sc| {
+            let user_deposits = sc.user_deposited_fees(1).get().into_payments();
+            assert_eq!(user_deposits.len(), 2);
+            assert_eq!(user_deposits.get(0).amount, managed_biguint!(998_990));
+            assert_eq!(
+                user_deposits.get(1).token_identifier,
+                managed_token_id!(WEGLD_TOKEN_ID)
+            );
+            assert_eq!(user_deposits.get(1).amount, managed_biguint!(10));
+        })
+        .assert_ok();
+}
+
+#[test]
+fn auto_swap_restrictions_test() {
+    let (b_mock_rc, mut pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    sub_sc
+        .call_add_accepted_fees_tokens(vec![WEGLD_TOKEN_ID.to_vec()])
+        .assert_ok();
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![(
+                Some(WEGLD_TOKEN_ID.to_vec()),
+                1_000,
+                false,
+                DAILY_SUBSCRIPTION_EPOCHS,
+            )],
+        )
+        .assert_ok();
+
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+
+    let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(1_000_000));
+    sub_sc
+        .call_deposit(&user, FIRST_TOKEN_ID, 1_000_000)
+        .assert_ok();
+    sub_sc.call_subscribe(&user, vec![(1, 0)]).assert_ok();
+    sub_sc.call_enable_auto_swap(&user, 100).assert_ok();
+
+    // tokens kept off the whitelist for the service are not swapped
+    sub_sc
+        .call_set_service_token_whitelist(&user, 1, vec![WEGLD_TOKEN_ID])
+        .assert_ok();
+
+    b_mock_rc.borrow_mut().set_block_epoch(10);
+    sub_sc
+        .call_subtract_payment_with_sc_error(&rand_service, 0, 1, SubtractError::TokenNotDeposited)
+        .assert_ok();
+
+    // swaps through an inactive pair are skipped instead of failing the charge
+    sub_sc
+        .call_set_service_token_whitelist(&user, 1, vec![WEGLD_TOKEN_ID, FIRST_TOKEN_ID])
+        .assert_ok();
+    let owner = sub_sc.owner_addr.clone();
+    pair_setup.set_state(&owner, State::Inactive);
+
+    sub_sc
+        .call_subtract_payment_batch(
+            &rand_service,
+            vec![(0, 1)],
+            0,
+            vec![ScResult::Err(SubtractError::TokenNotDeposited)],
+        )
+        .assert_ok();
+
+    pair_setup.set_state(&owner, State::Active);
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_ok();
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, WEGLD_TOKEN_ID, &rust_biguint!(1_000));
+}
+
+#[test]
+fn withdrawal_reserve_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![
+                (
+                    Some(FIRST_TOKEN_ID.to_vec()),
+                    1_000,
+                    false,
+                    DAILY_SUBSCRIPTION_EPOCHS,
+                ),
+                (None, 1_000, false, DAILY_SUBSCRIPTION_EPOCHS),
+            ],
+        )
+        .assert_ok();
+
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+    sub_sc
+        .call_set_withdrawal_reserve(&rand_service, 1, true)
+        .assert_user_error("Reserve requires a payment token");
+    sub_sc
+        .call_set_withdrawal_reserve(&rand_service, 0, true)
+        .assert_ok();
+
+    let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(1_000_000));
+    sub_sc
+        .call_deposit(&user, FIRST_TOKEN_ID, 1_000_000)
+        .assert_ok();
+    sub_sc.call_subscribe(&user, vec![(1, 0)]).assert_ok();
+
+    // the next cycle of 1,000 tokens is reserved
+    sub_sc
+        .call_withdraw_funds(&user, vec![(FIRST_TOKEN_ID.to_vec(), 999_500)])
+        .assert_user_error("Funds reserved for the next cycle");
+    sub_sc
+        .call_withdraw_funds(&user, vec![(FIRST_TOKEN_ID.to_vec(), 500_000)])
+        .assert_ok();
+
+    sub_sc
+        .call_request_withdrawal(&user, vec![(FIRST_TOKEN_ID.to_vec(), 499_500)])
+        .assert_user_error("Withdrawal delay not set");
+
+    sub_sc.call_set_withdrawal_delay(5).assert_ok();
+    sub_sc
+        .call_request_withdrawal(&user, vec![(OTHER_TOKEN_ID.to_vec(), 499_500)])
+        .assert_user_error("Invalid token");
+    sub_sc
+        .call_request_withdrawal(&user, vec![(FIRST_TOKEN_ID.to_vec(), 499_500)])
+        .assert_ok();
+
+    b_mock_rc.borrow_mut().set_block_epoch(4);
+    sub_sc
+        .call_claim_withdrawal(&user)
+        .assert_user_error("Withdrawal still locked");
+
+    // the service can still charge during the delay, and the claim is capped to the remaining balance
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_ok();
+
+    b_mock_rc.borrow_mut().set_block_epoch(5);
+    sub_sc.call_claim_withdrawal(&user).assert_ok();
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(999_000));
+    b_mock_rc
+        .borrow_mut()
+        .execute_query(&sub_sc.s_wrapper, |sc| {
+            assert!(sc.pending_withdrawal(1).is_empty());
+
+            let user_deposits = sc.user_deposited_fees(1).get().into_payments();
+            assert_eq!(user_deposits.get(0).amount, managed_biguint!(0));
+        })
+        .assert_ok();
+
+    // nothing is left to claim, so no tokens are sent
+    sub_sc
+        .call_request_withdrawal(&user, vec![(FIRST_TOKEN_ID.to_vec(), 1_000)])
+        .assert_ok();
+
+    b_mock_rc.borrow_mut().set_block_epoch(10);
+    sub_sc.call_claim_withdrawal(&user).assert_ok();
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(999_000));
+}
+
+#[test]
+fn withdrawal_reserve_other_token_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    sub_sc
+        .call_add_accepted_fees_tokens(vec![OTHER_TOKEN_ID.to_vec()])
+        .assert_ok();
+    sub_sc
+        .call_set_token_fixed_rate(OTHER_TOKEN_ID, 3 * PRICE_PRECISION)
+        .assert_ok();
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![(
+                Some(FIRST_TOKEN_ID.to_vec()),
+                1_000,
+                false,
+                DAILY_SUBSCRIPTION_EPOCHS,
+            )],
+        )
+        .assert_ok();
+
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+    sub_sc
+        .call_set_withdrawal_reserve(&rand_service, 0, true)
+        .assert_ok();
+
+    let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(1_000_000));
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, OTHER_TOKEN_ID, &rust_biguint!(3_000_000));
+    sub_sc
+        .call_deposit_multiple(
+            &user,
+            vec![(FIRST_TOKEN_ID, 1_000_000), (OTHER_TOKEN_ID, 3_000_000)],
+        )
+        .assert_ok();
+    sub_sc.call_subscribe(&user, vec![(1, 0)]).assert_ok();
+
+    sub_sc
+        .call_withdraw_funds(&user, vec![(FIRST_TOKEN_ID.to_vec(), 998_500)])
+        .assert_ok();
+
+    // after the charge, the user has less than the reserved cycle left
+    b_mock_rc.borrow_mut().set_block_epoch(10);
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_ok();
+
+    sub_sc
+        .call_withdraw_funds(&user, vec![(FIRST_TOKEN_ID.to_vec(), 100)])
+        .assert_user_error("Funds reserved for the next cycle");
+    sub_sc
+        .call_withdraw_funds(&user, vec![(OTHER_TOKEN_ID.to_vec(), 3_000_000)])
+        .assert_ok();
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&user, OTHER_TOKEN_ID, &rust_biguint!(3_000_000));
+}
+
+#[test]
+fn withdrawal_reserve_without_price_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![(
+                Some(FIRST_TOKEN_ID.to_vec()),
+                1_000,
+                true,
+                DAILY_SUBSCRIPTION_EPOCHS,
+            )],
+        )
+        .assert_ok();
+
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+    sub_sc
+        .call_set_withdrawal_reserve(&rand_service, 0, true)
+        .assert_ok();
+
+    let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(1_000_000));
+    sub_sc
+        .call_deposit(&user, FIRST_TOKEN_ID, 1_000_000)
+        .assert_ok();
+    sub_sc.call_subscribe(&user, vec![(1, 0)]).assert_ok();
+
+    sub_sc
+        .call_withdraw_funds(&user, vec![(FIRST_TOKEN_ID.to_vec(), 1_000_000)])
+        .assert_user_error("Funds reserved for the next cycle");
+
+    // without a price for the token, the next cycle is not reserved
+    b_mock_rc
+        .borrow_mut()
+        .execute_tx(&sub_sc.owner_addr, &sub_sc.s_wrapper, &rust_zero, |sc| {
+            sc.remove_pair_address(managed_token_id!(FIRST_TOKEN_ID));
+        })
+        .assert_ok();
+    sub_sc
+        .call_withdraw_funds(&user, vec![(FIRST_TOKEN_ID.to_vec(), 1_000_000)])
+        .assert_ok();
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(1_000_000));
+}
+
+#[test]
+fn cycle_escrow_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![(
+                Some(FIRST_TOKEN_ID.to_vec()),
+                1_000,
+                false,
+                DAILY_SUBSCRIPTION_EPOCHS,
+            )],
+        )
+        .assert_ok();
+
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+    sub_sc
+        .call_set_cycle_escrow(&rand_service, 0, true)
+        .assert_ok();
+
+    let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(2_000_000));
+    sub_sc
+        .call_deposit(&user, FIRST_TOKEN_ID, 1_000_000)
+        .assert_ok();
+    let tx_result = sub_sc.call_subscribe(&user, vec![(1, 0)]);
+    tx_result.assert_ok();
+
+    let escrow_logs = get_event_logs(&tx_result, b"escrowEvent");
+    assert_eq!(escrow_logs.len(), 1);
+    assert_eq!(
+        escrow_logs[0].data,
+        vec![encode_payment(FIRST_TOKEN_ID, 1_000)]
+    );
+
+    // the first cycle is moved into escrow, so it cannot be withdrawn
+    sub_sc.check_user_balances(&user, 999_000, 1_000);
+    sub_sc
+        .call_withdraw_funds(&user, vec![(FIRST_TOKEN_ID.to_vec(), 999_001)])
+        .assert_user_error("User balance not enough");
+    sub_sc
+        .call_withdraw_funds(&user, vec![(FIRST_TOKEN_ID.to_vec(), 999_000)])
+        .assert_ok();
+
+    // paid from escrow, while the next cycle cannot be escrowed from the empty free balance
+    b_mock_rc.borrow_mut().set_block_epoch(10);
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_ok();
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(1_000));
+    sub_sc.check_user_balances(&user, 0, 0);
+
+    // without escrow, the charge falls back to the free balance and escrows the next cycle
+    sub_sc
+        .call_deposit(&user, FIRST_TOKEN_ID, 1_000_000)
+        .assert_ok();
+    b_mock_rc.borrow_mut().set_block_epoch(11);
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_ok();
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(2_000));
+    sub_sc.check_user_balances(&user, 998_000, 1_000);
+
+    // unsubscribing releases the escrow
+    let tx_result = sub_sc.call_unsubscribe(&user, vec![(1, 0)]);
+    tx_result.assert_ok();
+    sub_sc.check_user_balances(&user, 999_000, 0);
+
+    let release_logs = get_event_logs(&tx_result, b"releaseEscrowEvent");
+    assert_eq!(release_logs.len(), 1);
+    assert_eq!(
+        release_logs[0].data,
+        vec![encode_payment(FIRST_TOKEN_ID, 1_000)]
+    );
+}
+
+#[test]
+fn cycle_escrow_with_option_update_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    sub_sc
+        .call_add_accepted_fees_tokens(vec![OTHER_TOKEN_ID.to_vec()])
+        .assert_ok();
+    sub_sc
+        .call_set_token_fixed_rate(OTHER_TOKEN_ID, 3 * PRICE_PRECISION)
+        .assert_ok();
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![(
+                Some(FIRST_TOKEN_ID.to_vec()),
+                1_000,
+                false,
+                DAILY_SUBSCRIPTION_EPOCHS,
+            )],
+        )
+        .assert_ok();
+
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+    sub_sc
+        .call_set_cycle_escrow(&rand_service, 0, true)
+        .assert_ok();
+
+    let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(1_000_000));
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, OTHER_TOKEN_ID, &rust_biguint!(3_000_000));
+    sub_sc
+        .call_deposit_multiple(
+            &user,
+            vec![(FIRST_TOKEN_ID, 1_000_000), (OTHER_TOKEN_ID, 3_000_000)],
+        )
+        .assert_ok();
+    sub_sc.call_subscribe(&user, vec![(1, 0)]).assert_ok();
+    sub_sc.check_user_balances(&user, 3_999_000, 1_000);
+
+    // the option now charges another token
+    sub_sc
+        .call_update_service_option(
+            &rand_service,
+            0,
+            (
+                Some(OTHER_TOKEN_ID.to_vec()),
+                1_000,
+                false,
+                DAILY_SUBSCRIPTION_EPOCHS,
+            ),
+        )
+        .assert_ok();
+    sub_sc
+        .call_approve_service_option_update(&rand_service, 0)
+        .assert_ok();
+
+    // the outdated escrow is released, and the charge follows the new terms
+    b_mock_rc.borrow_mut().set_block_epoch(10);
+    let tx_result = sub_sc.call_subtract_payment(&rand_service, 0, 1);
+    tx_result.assert_ok();
+
+    let release_logs = get_event_logs(&tx_result, b"releaseEscrowEvent");
+    assert_eq!(release_logs.len(), 1);
+    assert_eq!(
+        release_logs[0].data,
+        vec![encode_payment(FIRST_TOKEN_ID, 1_000)]
+    );
+
+    let charge_logs = get_event_logs(&tx_result, b"subtractPaymentEvent");
+    assert_eq!(charge_logs.len(), 1);
+    assert_eq!(
+        charge_logs[0].data,
+        vec![encode_payment(OTHER_TOKEN_ID, 1_000)]
+    );
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_zero);
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, OTHER_TOKEN_ID, &rust_biguint!(1_000));
+
+    // the next cycle is escrowed in the new token
+    sub_sc.check_user_balances(&user, 3_998_000, 1_000);
+    b_mock_rc
+        .borrow_mut()
+        .execute_query(&sub_sc.s_wrapper, |sc| {
+            assert_eq!(
+                sc.cycle_escrow(1, 1, 0).get().token_identifier,
+                managed_token_id!(OTHER_TOKEN_ID)
+            );
+        })
+        .assert_ok();
+}
+
+#[test]
+fn revenue_pull_mode_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![(
+                Some(FIRST_TOKEN_ID.to_vec()),
+                1_000,
+                false,
+                DAILY_SUBSCRIPTION_EPOCHS,
+            )],
+        )
+        .assert_ok();
+
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+    sub_sc
+        .call_set_revenue_pull_mode(&rand_service, true)
+        .assert_ok();
+
+    let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(1_000_000));
+    sub_sc
+        .call_deposit(&user, FIRST_TOKEN_ID, 1_000_000)
+        .assert_ok();
+    sub_sc.call_subscribe(&user, vec![(1, 0)]).assert_ok();
+
+    for epoch in [10, 11] {
+        b_mock_rc.borrow_mut().set_block_epoch(epoch);
+        sub_sc
+            .call_subtract_payment(&rand_service, 0, 1)
+            .assert_ok();
+    }
+
+    // nothing is sent to the service until it claims
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_zero);
+
+    let treasury = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_claim_service_revenue(&rand_service, &treasury)
+        .assert_ok();
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&treasury, FIRST_TOKEN_ID, &rust_biguint!(2_000));
+
+    sub_sc
+        .call_set_revenue_pull_mode(&rand_service, false)
+        .assert_ok();
+    b_mock_rc.borrow_mut().set_block_epoch(12);
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_ok();
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(1_000));
+
+    b_mock_rc
+        .borrow_mut()
+        .execute_query(&sub_sc.s_wrapper, |sc| {
+            assert!(sc.service_revenue(1).is_empty());
+
+            let lifetime_revenue = sc.service_lifetime_revenue(1).get().into_payments();
+            assert_eq!(lifetime_revenue.len(), 1);
+            assert_eq!(lifetime_revenue.get(0).amount, managed_biguint!(3_000));
+        })
+        .assert_ok();
+
+    // unclaimed revenue stays claimable by the last owner once the service unregisters
+    sub_sc
+        .call_set_revenue_pull_mode(&rand_service, true)
+        .assert_ok();
+    b_mock_rc.borrow_mut().set_block_epoch(13);
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_ok();
+    sub_sc
+        .call_claim_unregistered_service_revenue(&rand_service, 1, &treasury)
+        .assert_user_error("No revenue to claim");
+    sub_sc.call_unregister_service(&rand_service).assert_ok();
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&rand_service, FIRST_TOKEN_ID, &rust_biguint!(1_000));
+    b_mock_rc
+        .borrow_mut()
+        .execute_query(&sub_sc.s_wrapper, |sc| {
+            assert!(!sc.revenue_pull_mode(1).get());
+            assert_eq!(
+                sc.unregistered_service_revenue_owner(1).get(),
+                managed_address!(&rand_service)
+            );
+        })
+        .assert_ok();
+
+    sub_sc
+        .call_claim_unregistered_service_revenue(&user, 1, &user)
+        .assert_user_error("Only the service owner can call this");
+    sub_sc
+        .call_claim_unregistered_service_revenue(&rand_service, 1, &treasury)
+        .assert_ok();
+    sub_sc
+        .call_claim_unregistered_service_revenue(&rand_service, 1, &treasury)
+        .assert_user_error("No revenue to claim");
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&treasury, FIRST_TOKEN_ID, &rust_biguint!(3_000));
+    b_mock_rc
+        .borrow_mut()
+        .execute_query(&sub_sc.s_wrapper, |sc| {
+            assert!(sc.service_revenue(1).is_empty());
+            assert!(sc.unregistered_service_revenue_owner(1).is_empty());
+        })
+        .assert_ok();
+}
+
+#[test]
+fn service_ownership_transfer_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    let rand_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &rand_service,
+            vec![(
+                Some(FIRST_TOKEN_ID.to_vec()),
+                1_000,
+                false,
+                DAILY_SUBSCRIPTION_EPOCHS,
+            )],
+        )
+        .assert_ok();
+    sub_sc.call_approve_service(&rand_service).assert_ok();
+
+    let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(1_000_000));
+    sub_sc
+        .call_deposit(&user, FIRST_TOKEN_ID, 1_000_000)
+        .assert_ok();
+    sub_sc.call_subscribe(&user, vec![(1, 0)]).assert_ok();
+
+    // two-step ownership transfer
+    let new_owner = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_transfer_service_ownership(&new_owner, 1, &new_owner)
+        .assert_user_error("Only the service owner can call this");
+    sub_sc
+        .call_transfer_service_ownership(&rand_service, 1, &new_owner)
+        .assert_ok();
+    sub_sc
+        .call_accept_service_ownership(&user, 1)
+        .assert_user_error("Not the pending owner");
+    sub_sc
+        .call_accept_service_ownership(&new_owner, 1)
+        .assert_ok();
+    sub_sc
+        .call_transfer_service_ownership(&rand_service, 1, &rand_service)
+        .assert_user_error("Only the service owner can call this");
+
+    let treasury = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_set_service_payout_address(&new_owner, 1, &treasury)
+        .assert_ok();
+
+    // the service moves to a new address, keeping its ID and subscribers
+    let new_service_address = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_change_service_address(&new_owner, 1, &Address::zero())
+        .assert_user_error("Invalid service address");
+    sub_sc
+        .call_change_service_address(&new_owner, 1, &new_service_address)
+        .assert_ok();
+
+    b_mock_rc.borrow_mut().set_block_epoch(10);
+    sub_sc
+        .call_subtract_payment(&rand_service, 0, 1)
+        .assert_user_error("Unknown address");
+    sub_sc
+        .call_subtract_payment(&new_service_address, 0, 1)
+        .assert_ok();
+
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&treasury, FIRST_TOKEN_ID, &rust_biguint!(1_000));
+    b_mock_rc
+        .borrow()
+        .check_esdt_balance(&new_service_address, FIRST_TOKEN_ID, &rust_zero);
+
+    b_mock_rc
+        .borrow_mut()
+        .execute_query(&sub_sc.s_wrapper, |sc| {
+            assert_eq!(
+                sc.get_service_id(&managed_address!(&new_service_address)),
+                1
+            );
+            assert_eq!(sc.get_service_id(&managed_address!(&rand_service)), 0);
+            assert_eq!(
+                sc.get_service_address(1),
+                Some(managed_address!(&new_service_address))
+            );
+
+            let profile = sc.get_service_profile(1);
+            assert_eq!(profile.owner, managed_address!(&new_owner));
+            assert_eq!(profile.payout_address, managed_address!(&treasury));
+            assert_eq!(sc.subscribed_users(1, 0).len(), 1);
+        })
+        .assert_ok();
+
+    // the original address still maps to the service ID, so the service can move back to it
+    sub_sc
+        .call_change_service_address(&new_owner, 1, &rand_service)
+        .assert_ok();
+    sub_sc
+        .call_subtract_payment(&new_service_address, 0, 1)
+        .assert_user_error("Unknown address");
+
+    b_mock_rc
+        .borrow_mut()
+        .execute_query(&sub_sc.s_wrapper, |sc| {
+            assert_eq!(sc.get_service_id(&managed_address!(&rand_service)), 1);
+            assert_eq!(
+                sc.get_service_id(&managed_address!(&new_service_address)),
+                0
+            );
+            assert_eq!(
+                sc.get_service_address(1),
+                Some(managed_address!(&rand_service))
+            );
+        })
+        .assert_ok();
+}
+
+#[test]
+fn service_listings_test() {
+    let (b_mock_rc, _pair_setup, mut sub_sc) =
+        init_all(pair::contract_obj, subscription_fee::contract_obj);
+    let rust_zero = rust_biguint!(0);
+
+    let first_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service_with_metadata(
+            &first_service,
+            b"Oracle",
+            b"bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi",
+            b"https://oracle.example",
+            b"data",
+            vec![
+                (
+                    Some(FIRST_TOKEN_ID.to_vec()),
+                    1_000,
+                    false,
+                    DAILY_SUBSCRIPTION_EPOCHS,
+                    &b"Daily"[..],
+                ),
+                (Some(FIRST_TOKEN_ID.to_vec()), 5_000, false, 30, &[]),
+            ],
+        )
+        .assert_ok();
+    sub_sc.call_approve_service(&first_service).assert_ok();
+
+    let second_service = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    sub_sc
+        .call_register_service(
+            &second_service,
+            vec![(
+                Some(FIRST_TOKEN_ID.to_vec()),
+                1_000,
+                false,
+                DAILY_SUBSCRIPTION_EPOCHS,
+            )],
+        )
+        .assert_ok();
+    sub_sc.call_approve_service(&second_service).assert_ok();
+
+    sub_sc
+        .call_set_service_option_label(&second_service, 1, 1, b"Monthly")
+        .assert_user_error("Only the service owner can call this");
+    sub_sc
+        .call_set_service_option_label(&first_service, 1, 1, b"Monthly")
+        .assert_ok();
+
+    let user = b_mock_rc.borrow_mut().create_user_account(&rust_zero);
+    b_mock_rc
+        .borrow_mut()
+        .set_esdt_balance(&user, FIRST_TOKEN_ID, &rust_biguint!(1_000_000));
+    sub_sc
+        .call_deposit(&user, FIRST_TOKEN_ID, 1_000_000)
+        .assert_ok();
+    sub_sc
+        .call_subscribe(&user, vec![(1, 0), (2, 0)])
+        .assert_ok();
+
+    b_mock_rc
+        .borrow_mut()
+        .execute_query(&sub_sc.s_wrapper, |sc| {
+            let listings: Vec<_> = sc
+                .get_service_listings(1, 10, OptionalValue::None)
+                .into_iter()
+                .collect();
+            assert_eq!(listings.len(), 2);
+            assert_eq!(listings[1].metadata.name, managed_buffer!(b""));
+
+            let listings: Vec<_> = sc
+                .get_service_listings(1, 10, OptionalValue::Some(managed_buffer!(b"data")))
+                .into_iter()
+                .collect();
+            assert_eq!(listings.len(), 1);
+
+            let listing = &listings[0];
+            assert_eq!(listing.service_id, 1);
+            assert_eq!(listing.metadata.name, managed_buffer!(b"Oracle"));
+            assert_eq!(
+                listing.metadata.url,
+                managed_buffer!(b"https://oracle.example")
+            );
+            assert_eq!(listing.options.len(), 2);
+
+            let daily_option = listing.options.get(0);
+            assert_eq!(daily_option.label, managed_buffer!(b"Daily"));
+            assert_eq!(daily_option.subscriber_count, 1);
+
+            let monthly_option = listing.options.get(1);
+            assert_eq!(monthly_option.label, managed_buffer!(b"Monthly"));
+            assert_eq!(monthly_option.subscriber_count, 0);
+        })
+        .assert_ok();
+}
@@ -5,9 +5,9 @@
 ////////////////////////////////////////////////////
 
 // Init:                                 1
-// Endpoints:                           21
+// Endpoints:                          125
 // Async Callback (empty):               1
-// Total number of exported functions:  23
+// Total number of exported functions: 127
 
 #![no_std]
 
@@ -25,24 +25,128 @@ multiversx_sc_wasm_adapter::endpoints! {
         upgrade => upgrade
         addAcceptedFeesTokens => add_accepted_fees_tokens
         setMinDepositValue => set_min_deposit_value
+        setProtocolFeePercentage => set_protocol_fee_percentage
+        setEgldWrapperAddress => set_egld_wrapper_address
+        setWithdrawalDelay => set_withdrawal_delay
+        setProtocolFeesClaimAddress => set_protocol_fees_claim_address
+        claimProtocolFees => claim_protocol_fees
         deposit => deposit
         withdrawFunds => withdraw_funds
+        setTokenPreferenceOrder => set_token_preference_order
+        setExcludedTokens => set_excluded_tokens
+        requestWithdrawal => request_withdrawal
+        claimWithdrawal => claim_withdrawal
         getAcceptedFeesTokens => accepted_fees_tokens
         getUserDepositedFees => user_deposited_fees
+        getUserTokenPreferenceOrder => user_token_preference_order
+        getUserExcludedTokens => user_excluded_tokens
+        getAutoSwapMaxSlippage => auto_swap_max_slippage
         getMinStableTokenDepositValue => min_stable_token_deposit_value
+        getServiceOptionLabel => service_option_label
+        getPendingServiceMetadata => pending_service_metadata
+        getPendingServiceOwner => pending_service_owner
         getPendingServices => pending_services
         getServiceInfo => service_info
+        getPendingServiceOptionUpdate => pending_service_option_update
+        getServiceOptionVersion => service_option_version
+        isServiceOptionDeprecated => deprecated_service_option
+        getUserOptionVersion => user_option_version
         getSubscribedUsers => subscribed_users
+        getProratedRefundsEnabled => prorated_refunds_enabled
+        isCycleEscrowEnabled => cycle_escrow_enabled
+        getCycleEscrow => cycle_escrow
+        isWithdrawalReserveEnabled => withdrawal_reserve_enabled
+        getWithdrawalDelayEpochs => withdrawal_delay_epochs
+        getPendingWithdrawal => pending_withdrawal
+        getServiceTrialEpochs => service_trial_epochs
+        hasUsedTrial => used_trial
+        getEnergyDiscountTiers => energy_discount_tiers
+        getLastCharge => last_charge
+        getPendingRefunds => pending_refunds
+        getUsersWithPendingRefunds => users_with_pending_refunds
+        getServiceGracePeriod => service_grace_period
+        getFailedChargeAttempts => failed_charge_attempts
+        getPastDueEpoch => past_due_epoch
+        getSubtractBatchProgress => subtract_batch_progress
+        isAutomaticBillingEnabled => automatic_billing_enabled
+        getTokenPriceRoute => token_price_route
+        getTokenPriceSource => token_price_source
+        getTokenPriceFeedNames => token_price_feed_names
+        getTokenDecimals => token_decimals
+        getPriceAggregatorAddress => price_aggregator_address
+        getMaxPriceStaleness => max_price_staleness
+        getMaxPriceDeviation => max_price_deviation
+        isRevenuePullModeEnabled => revenue_pull_mode
+        getServiceRevenue => service_revenue
+        getUnregisteredServiceRevenueOwner => unregistered_service_revenue_owner
+        getServiceLifetimeRevenue => service_lifetime_revenue
+        getProtocolFeePercentage => protocol_fee_percentage
+        getProtocolFees => protocol_fees
+        getProtocolFeesClaimAddress => protocol_fees_claim_address
+        getSpendingCap => spending_cap
+        getSpendingInPeriod => spending_in_period
+        getServiceTokenWhitelist => service_token_whitelist
+        getEgldWrapperAddress => egld_wrapper_address
         registerService => register_service
+        registerServiceWithMetadata => register_service_with_metadata
         addExtraServices => add_extra_services
+        updateServiceOption => update_service_option
+        approveServiceOptionUpdate => approve_service_option_update
+        deprecateServiceOption => deprecate_service_option
         unregisterService => unregister_service
         unregisterServiceByOwner => unregister_service_by_owner
         approveService => approve_service
+        setGracePeriod => set_grace_period
+        setAutomaticBilling => set_automatic_billing
+        setProratedRefunds => set_prorated_refunds
+        setWithdrawalReserve => set_withdrawal_reserve
+        setTrialPeriod => set_trial_period
+        setEnergyDiscountTiers => set_energy_discount_tiers
+        payRefunds => pay_refunds
+        backfillUserSubscriptions => backfill_user_subscriptions
         subscribe => subscribe
         unsubscribe => unsubscribe
+        subscribeAndDeposit => subscribe_and_deposit
+        unsubscribeAll => unsubscribe_all
         subtractPayment => subtract_payment
+        subtractPaymentBatch => subtract_payment_batch
+        processDueSubscriptions => process_due_subscriptions
         addPairAddress => add_pair_address
         removePairAddress => remove_pair_address
+        setTokenPriceRoute => set_token_price_route
+        removeTokenPriceRoute => remove_token_price_route
+        setPriceAggregatorAddress => set_price_aggregator_address
+        setTokenDecimals => set_token_decimals
+        setTokenPriceSource => set_token_price_source
+        setMaxPriceStaleness => set_max_price_staleness
+        setMaxPriceDeviation => set_max_price_deviation
+        getServices => get_services
+        getServiceListings => get_service_listings
+        getServiceSubscribers => get_service_subscribers
+        getUserSubscriptions => get_user_subscriptions
+        pause => pause_endpoint
+        unpause => unpause_endpoint
+        isPaused => paused_status
+        setEnergyFactoryAddress => set_energy_factory_address
+        getEnergyFactoryAddress => energy_factory_address
+        setSpendingCap => set_spending_cap
+        removeSpendingCap => remove_spending_cap
+        setServiceTokenWhitelist => set_service_token_whitelist
+        enableAutoSwap => enable_auto_swap
+        disableAutoSwap => disable_auto_swap
+        setCycleEscrow => set_cycle_escrow
+        getUserBalances => get_user_balances
+        setRevenuePullMode => set_revenue_pull_mode
+        claimServiceRevenue => claim_service_revenue
+        claimUnregisteredServiceRevenue => claim_unregistered_service_revenue
+        setServicePayoutAddress => set_service_payout_address
+        setServiceMetadata => set_service_metadata
+        setServiceOptionLabel => set_service_option_label
+        transferServiceOwnership => transfer_service_ownership
+        acceptServiceOwnership => accept_service_ownership
+        changeServiceAddress => change_service_address
+        getServiceAddress => get_service_address
+        getServiceProfile => get_service_profile
     )
 }
 